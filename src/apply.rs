@@ -0,0 +1,247 @@
+//! Bring the board into a desired state, sending only the parts that differ.
+//!
+//! The device only accepts complete lists, so the smallest possible change is replacing the netlist,
+//! the bridgelist or the supply switch position. Parts that already match are not sent at all.
+
+use crate::{
+    board::Board,
+    diff::{self, Change, PartialState},
+    types::{Bridgelist, Net, SupplySwitchPos},
+};
+use anyhow::Result;
+
+/// A part of the state that needs to be sent, to reach the target
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    SupplySwitch(SupplySwitchPos),
+    Bridgelist(Bridgelist),
+    Netlist(Vec<Net>),
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::SupplySwitch(pos) => write!(f, "set supply switch to {}", pos),
+            Step::Bridgelist(bridgelist) => {
+                write!(f, "upload bridgelist ({} bridges)", bridgelist.len())
+            }
+            Step::Netlist(netlist) => write!(f, "upload netlist ({} nets)", netlist.len()),
+        }
+    }
+}
+
+/// What needs to change to reach the target, and how
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    /// In the order they are sent: supply switch, bridges, nets
+    pub steps: Vec<Step>,
+}
+
+/// Outcome of [`apply`]
+#[derive(Debug)]
+pub struct Report {
+    pub plan: Plan,
+    /// Changes from the target to the state read back after applying it (e.g. nodes the firmware
+    /// could not route): removals are missing on the board, additions only exist on the board.
+    /// Empty if the target was reached.
+    pub mismatches: Vec<Change>,
+}
+
+/// Read the parts of the board's state that the target specifies
+fn read(board: &mut dyn Board, target: &PartialState) -> Result<PartialState> {
+    Ok(PartialState {
+        netlist: target
+            .netlist
+            .as_ref()
+            .map(|_| board.netlist())
+            .transpose()?,
+        bridgelist: target
+            .bridgelist
+            .as_ref()
+            .map(|_| board.bridgelist())
+            .transpose()?,
+        supply_switch: target
+            .supply_switch
+            .map(|_| board.supply_switch())
+            .transpose()?,
+    })
+}
+
+/// Read the relevant parts of the board's state, and determine what needs to be sent
+pub fn plan(board: &mut dyn Board, target: &PartialState) -> Result<Plan> {
+    Ok(compare(&read(board, target)?, target))
+}
+
+/// Send what is needed to reach the target, then read the state back to verify it
+pub fn apply(board: &mut dyn Board, target: &PartialState) -> Result<Report> {
+    let plan = plan(board, target)?;
+    for step in &plan.steps {
+        match step.clone() {
+            Step::SupplySwitch(pos) => board.set_supply_switch(pos)?,
+            Step::Bridgelist(bridgelist) => board.set_bridgelist(bridgelist)?,
+            Step::Netlist(netlist) => board.set_netlist(netlist)?,
+        }
+    }
+    // nothing was sent, so there is nothing to verify
+    if plan.steps.is_empty() {
+        return Ok(Report {
+            plan,
+            mismatches: vec![],
+        });
+    }
+    let mismatches = diff::partial(target, &read(board, target)?);
+    Ok(Report { plan, mismatches })
+}
+
+/// Determine the changes from `current` to `target`, and the steps needed to make them
+fn compare(current: &PartialState, target: &PartialState) -> Plan {
+    let changes = diff::partial(current, target);
+    let mut steps = vec![];
+    if changes
+        .iter()
+        .any(|change| matches!(change, Change::SupplySwitchChanged { .. }))
+    {
+        steps.extend(target.supply_switch.map(Step::SupplySwitch));
+    }
+    if changes.iter().any(|change| {
+        matches!(
+            change,
+            Change::BridgeAdded { .. } | Change::BridgeRemoved { .. }
+        )
+    }) {
+        steps.extend(target.bridgelist.clone().map(Step::Bridgelist));
+    }
+    if changes.iter().any(|change| {
+        matches!(
+            change,
+            Change::NetAdded { .. }
+                | Change::NetRemoved { .. }
+                | Change::NodesAdded { .. }
+                | Change::NodesRemoved { .. }
+                | Change::NetRenamed { .. }
+                | Change::NetRecolored { .. }
+        )
+    }) {
+        steps.extend(target.netlist.clone().map(Step::Netlist));
+    }
+    Plan { changes, steps }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board::DryRunBoard,
+        state::BoardState,
+        types::{ChipStatus, Color, Message, Node},
+    };
+
+    /// A board that cannot route one of the bridges
+    struct Unroutable {
+        board: DryRunBoard,
+        bridge: (Node, Node),
+    }
+
+    impl Board for Unroutable {
+        fn netlist(&mut self) -> Result<Vec<Net>> {
+            self.board.netlist()
+        }
+        fn set_netlist(&mut self, netlist: Vec<Net>) -> Result<()> {
+            self.board.set_netlist(netlist)
+        }
+        fn bridgelist(&mut self) -> Result<Bridgelist> {
+            self.board.bridgelist()
+        }
+        fn set_bridgelist(&mut self, mut bridgelist: Bridgelist) -> Result<()> {
+            bridgelist.retain(|bridge| *bridge != self.bridge);
+            self.board.set_bridgelist(bridgelist)
+        }
+        fn supply_switch(&mut self) -> Result<SupplySwitchPos> {
+            self.board.supply_switch()
+        }
+        fn set_supply_switch(&mut self, pos: SupplySwitchPos) -> Result<()> {
+            self.board.set_supply_switch(pos)
+        }
+        fn chipstatus(&mut self) -> Result<Vec<ChipStatus>> {
+            self.board.chipstatus()
+        }
+        fn lightnet(&mut self, name: String, color: Color) -> Result<()> {
+            self.board.lightnet(name, color)
+        }
+        fn raw(&mut self, instruction: String, args: String) -> Result<(bool, Vec<Message>)> {
+            self.board.raw(instruction, args)
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let current = PartialState {
+            netlist: None,
+            bridgelist: Some(vec![
+                (Node::Column(3), Node::Column(7)),
+                (Node::GND, Node::Column(17)),
+            ]),
+            supply_switch: Some(SupplySwitchPos::V5),
+        };
+
+        // same bridges (in different order and direction), same supply switch
+        let target = PartialState {
+            netlist: None,
+            bridgelist: Some(vec![
+                (Node::Column(17), Node::GND),
+                (Node::Column(3), Node::Column(7)),
+            ]),
+            supply_switch: Some(SupplySwitchPos::V5),
+        };
+        let plan = compare(&current, &target);
+        assert!(plan.changes.is_empty());
+        assert!(plan.steps.is_empty());
+
+        let target = PartialState {
+            bridgelist: Some(vec![(Node::Column(3), Node::Column(7))]),
+            ..target
+        };
+        let plan = compare(&current, &target);
+        assert_eq!(
+            plan.changes,
+            vec![Change::BridgeRemoved {
+                from: Node::GND,
+                to: Node::Column(17)
+            }]
+        );
+        assert_eq!(
+            plan.steps,
+            vec![Step::Bridgelist(vec![(Node::Column(3), Node::Column(7))])]
+        );
+    }
+
+    #[test]
+    fn test_apply_reports_missing_bridge() {
+        let mut board = Unroutable {
+            board: DryRunBoard::new(BoardState {
+                netlist: vec![],
+                bridgelist: vec![],
+                supply_switch: SupplySwitchPos::V5,
+            }),
+            bridge: (Node::GND, Node::Column(17)),
+        };
+        let target = PartialState {
+            netlist: None,
+            bridgelist: Some(vec![
+                (Node::Column(3), Node::Column(7)),
+                (Node::GND, Node::Column(17)),
+            ]),
+            supply_switch: None,
+        };
+        let report = apply(&mut board, &target).unwrap();
+        // in the file, but missing on the board
+        assert_eq!(
+            report.mismatches,
+            vec![Change::BridgeRemoved {
+                from: Node::GND,
+                to: Node::Column(17)
+            }]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{
+    device::{self, Device, Instruction},
+    logger::DeviceLogger,
+    state::BoardState,
+    types::{Bridgelist, ChipStatus, Color, Message, Net, SupplySwitchPos},
+};
+use anyhow::Result;
+
+#[cfg(feature = "client")]
+use crate::client::Client;
+
+/// Operations on a Jumperless.
+///
+/// Implemented by [`Device`] (talking to the board via serial port), and by
+/// [`crate::client::Client`] (talking to a running `jlctl server`), so the CLI
+/// can work with either.
+pub trait Board {
+    fn netlist(&mut self) -> Result<Vec<Net>>;
+    fn set_netlist(&mut self, netlist: Vec<Net>) -> Result<()>;
+    fn bridgelist(&mut self) -> Result<Bridgelist>;
+    fn set_bridgelist(&mut self, bridgelist: Bridgelist) -> Result<()>;
+    /// Add bridges, returning the resulting list of bridges
+    fn add_bridges(&mut self, bridges: Bridgelist) -> Result<Bridgelist> {
+        let combined = device::bridges_added(self.bridgelist()?, bridges);
+        self.set_bridgelist(combined.clone())?;
+        Ok(combined)
+    }
+    /// Remove bridges, returning the resulting list of bridges
+    fn remove_bridges(&mut self, bridges: Bridgelist) -> Result<Bridgelist> {
+        let remaining = device::bridges_removed(self.bridgelist()?, &bridges);
+        self.set_bridgelist(remaining.clone())?;
+        Ok(remaining)
+    }
+    fn supply_switch(&mut self) -> Result<SupplySwitchPos>;
+    fn set_supply_switch(&mut self, pos: SupplySwitchPos) -> Result<()>;
+    fn chipstatus(&mut self) -> Result<Vec<ChipStatus>>;
+    fn lightnet(&mut self, name: String, color: Color) -> Result<()>;
+    fn raw(&mut self, instruction: String, args: String) -> Result<(bool, Vec<Message>)>;
+}
+
+impl<L: DeviceLogger> Board for Device<L> {
+    fn netlist(&mut self) -> Result<Vec<Net>> {
+        Device::netlist(self)
+    }
+
+    fn set_netlist(&mut self, netlist: Vec<Net>) -> Result<()> {
+        Device::set_netlist(self, netlist)
+    }
+
+    fn bridgelist(&mut self) -> Result<Bridgelist> {
+        Device::bridgelist(self)
+    }
+
+    fn set_bridgelist(&mut self, bridgelist: Bridgelist) -> Result<()> {
+        Device::set_bridgelist(self, bridgelist)
+    }
+
+    fn add_bridges(&mut self, bridges: Bridgelist) -> Result<Bridgelist> {
+        Device::add_bridges(self, bridges)
+    }
+
+    fn remove_bridges(&mut self, bridges: Bridgelist) -> Result<Bridgelist> {
+        Device::remove_bridges(self, bridges)
+    }
+
+    fn supply_switch(&mut self) -> Result<SupplySwitchPos> {
+        Device::supply_switch(self)
+    }
+
+    fn set_supply_switch(&mut self, pos: SupplySwitchPos) -> Result<()> {
+        Device::set_supply_switch(self, pos)
+    }
+
+    fn chipstatus(&mut self) -> Result<Vec<ChipStatus>> {
+        Device::chipstatus(self)
+    }
+
+    fn lightnet(&mut self, name: String, color: Color) -> Result<()> {
+        Device::lightnet(self, name, color)
+    }
+
+    fn raw(&mut self, instruction: String, args: String) -> Result<(bool, Vec<Message>)> {
+        Device::raw(self, instruction, args)
+    }
+}
+
+#[cfg(feature = "client")]
+impl Board for Client {
+    fn netlist(&mut self) -> Result<Vec<Net>> {
+        Ok(Client::netlist(self)?)
+    }
+
+    fn set_netlist(&mut self, netlist: Vec<Net>) -> Result<()> {
+        Ok(Client::set_netlist(self, netlist)?)
+    }
+
+    fn bridgelist(&mut self) -> Result<Bridgelist> {
+        Ok(Client::bridgelist(self)?)
+    }
+
+    fn set_bridgelist(&mut self, bridgelist: Bridgelist) -> Result<()> {
+        Client::set_bridgelist(self, bridgelist)?;
+        Ok(())
+    }
+
+    fn add_bridges(&mut self, bridges: Bridgelist) -> Result<Bridgelist> {
+        Ok(Client::add_bridges(self, bridges)?)
+    }
+
+    fn remove_bridges(&mut self, bridges: Bridgelist) -> Result<Bridgelist> {
+        Ok(Client::remove_bridges(self, bridges)?)
+    }
+
+    fn supply_switch(&mut self) -> Result<SupplySwitchPos> {
+        Ok(Client::supply_switch(self)?)
+    }
+
+    fn set_supply_switch(&mut self, pos: SupplySwitchPos) -> Result<()> {
+        Ok(Client::set_supply_switch(self, pos)?)
+    }
+
+    fn chipstatus(&mut self) -> Result<Vec<ChipStatus>> {
+        Ok(Client::chipstatus(self)?)
+    }
+
+    fn lightnet(&mut self, name: String, color: Color) -> Result<()> {
+        Ok(Client::lightnet(self, &name, color)?)
+    }
+
+    fn raw(&mut self, instruction: String, args: String) -> Result<(bool, Vec<Message>)> {
+        Ok(Client::raw(self, instruction, args)?)
+    }
+}
+
+/// A board that only prints the lines that would be sent to the device (`--dry-run`).
+///
+/// Reads are answered from the given state, which is updated by writes, so later reads reflect earlier writes.
+pub struct DryRunBoard {
+    state: BoardState,
+    sequence: u32,
+}
+
+impl DryRunBoard {
+    pub fn new(state: BoardState) -> Self {
+        Self { state, sequence: 0 }
+    }
+
+    /// Print the instruction, numbered the same way [`Device`] does
+    fn send(&mut self, instruction: Instruction) {
+        self.sequence += 1;
+        println!("{}", instruction.generate(self.sequence));
+    }
+}
+
+impl Board for DryRunBoard {
+    fn netlist(&mut self) -> Result<Vec<Net>> {
+        self.send(Instruction::GetNetlist);
+        Ok(self.state.netlist.clone())
+    }
+
+    fn set_netlist(&mut self, netlist: Vec<Net>) -> Result<()> {
+        self.send(Instruction::SetNetlist(netlist.clone()));
+        self.state.netlist = netlist;
+        Ok(())
+    }
+
+    fn bridgelist(&mut self) -> Result<Bridgelist> {
+        self.send(Instruction::GetBridgelist);
+        Ok(self.state.bridgelist.clone())
+    }
+
+    fn set_bridgelist(&mut self, bridgelist: Bridgelist) -> Result<()> {
+        self.send(Instruction::SetBridgelist(bridgelist.clone()));
+        self.state.bridgelist = bridgelist;
+        Ok(())
+    }
+
+    fn supply_switch(&mut self) -> Result<SupplySwitchPos> {
+        self.send(Instruction::GetSupplySwitch);
+        Ok(self.state.supply_switch)
+    }
+
+    fn set_supply_switch(&mut self, pos: SupplySwitchPos) -> Result<()> {
+        self.send(Instruction::SetSupplySwitch(pos));
+        self.state.supply_switch = pos;
+        Ok(())
+    }
+
+    /// The chip status cannot be derived from the state, so this is always empty
+    fn chipstatus(&mut self) -> Result<Vec<ChipStatus>> {
+        self.send(Instruction::GetChipStatus);
+        Ok(vec![])
+    }
+
+    fn lightnet(&mut self, name: String, color: Color) -> Result<()> {
+        self.send(Instruction::Lightnet(name, color));
+        Ok(())
+    }
+
+    /// Responses to raw instructions are unknown, so this always reports success without messages
+    fn raw(&mut self, instruction: String, args: String) -> Result<(bool, Vec<Message>)> {
+        self.send(Instruction::Raw(instruction, args));
+        Ok((true, vec![]))
+    }
+}
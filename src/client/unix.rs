@@ -0,0 +1,134 @@
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+/// Perform a single HTTP/1.1 request over a Unix domain socket, returning status code and body.
+///
+/// This only supports what is needed to talk to `jlctl server`: one request per connection,
+/// and responses with either a `Content-Length`, or chunked encoding.
+pub fn request(
+    socket: &Path,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+    timeout: Duration,
+) -> io::Result<(u16, String)> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nAccept: application/json\r\n",
+        method, path
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if let Some(body) = body {
+        head.push_str(&format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n",
+            body.len()
+        ));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    if let Some(body) = body {
+        stream.write_all(body)?;
+    }
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response)?;
+    parse_response(&response)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn parse_response(response: &[u8]) -> io::Result<(u16, String)> {
+    let head_len = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("Incomplete response"))?;
+    let head =
+        std::str::from_utf8(&response[..head_len]).map_err(|_| invalid("Invalid response head"))?;
+    let mut body = &response[head_len + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("Invalid status line"))?;
+
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        } else if name.eq_ignore_ascii_case("content-length") {
+            let len: usize = value
+                .parse()
+                .map_err(|_| invalid("Invalid Content-Length"))?;
+            body = &body[..len.min(body.len())];
+        }
+    }
+
+    let body = if chunked {
+        decode_chunked(body)?
+    } else {
+        body.to_vec()
+    };
+    let body = String::from_utf8(body).map_err(|_| invalid("Response body is not UTF-8"))?;
+    Ok((status, body))
+}
+
+fn decode_chunked(mut input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = vec![];
+    loop {
+        let line_len = input
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid("Incomplete chunk"))?;
+        let size = std::str::from_utf8(&input[..line_len])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| invalid("Invalid chunk size"))?;
+        input = &input[line_len + 2..];
+        if size == 0 {
+            return Ok(output);
+        }
+        if input.len() < size + 2 {
+            return Err(invalid("Incomplete chunk"));
+        }
+        output.extend_from_slice(&input[..size]);
+        input = &input[size + 2..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let response =
+            b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\ncontent-type: application/json\r\n\r\ntrue";
+        assert_eq!(parse_response(response).unwrap(), (200, "true".to_string()));
+
+        let response =
+            b"HTTP/1.1 503 Service Unavailable\r\ntransfer-encoding: chunked\r\n\r\n3\r\n[1,\r\n2\r\n2]\r\n0\r\n\r\n";
+        assert_eq!(
+            parse_response(response).unwrap(),
+            (503, "[1,2]".to_string())
+        );
+
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+}
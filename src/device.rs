@@ -1,13 +1,15 @@
 use crate::logger::DeviceLogger;
+use crate::metrics::METRICS;
 use crate::parser;
-use crate::types::{Bridgelist, ChipStatus, Color, Message, Net, SupplySwitchPos};
+use crate::types::{Bridgelist, ChipStatus, Color, Message, Net, Node, SupplySwitchPos};
 use anyhow::{Context, Result};
 use serialport::SerialPort;
+use std::collections::BTreeMap;
 use std::io::{BufRead, BufReader, Write};
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::thread::{spawn, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const PORT_TIMEOUT: Duration = Duration::from_millis(450);
 const RESPONSE_TIMEOUT: Duration = Duration::from_millis(4000);
@@ -18,13 +20,40 @@ pub struct Device<L: DeviceLogger> {
     logger: L,
     reader: Option<(JoinHandle<()>, Receiver<Received>, Sender<()>)>,
     sequence: AtomicU32,
+    /// Instruction that was sent last, and when. Taken once the device responded, to record the round-trip time.
+    in_flight: Option<(&'static str, Instant)>,
+    /// Lights set via [`Device::lightnet`]. The device cannot report these, so they are tracked here.
+    lights: BTreeMap<String, Color>,
 }
 
+/// Errors that can occur when communicating with the device
+#[derive(Debug)]
+pub enum Error {
+    /// The device could not be found or opened, or the connection was lost
+    NotConnected(String),
+    /// The device did not respond in time
+    Timeout,
+    /// The device responded with an error, or with something unexpected
+    Protocol(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotConnected(reason) => write!(f, "Device not connected: {}", reason),
+            Error::Timeout => write!(f, "Timeout while receiving reply"),
+            Error::Protocol(reason) => write!(f, "Protocol error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Debug)]
 enum Received {
     Message(Message),
     Unrecognized(String),
-    Error(String),
+    Error(Error),
 }
 
 fn parse_received(line: String) -> Received {
@@ -32,13 +61,35 @@ fn parse_received(line: String) -> Received {
         Ok((_, message)) => Received::Message(message),
         Err(err) => {
             eprintln!("Error recognizing line: {:?}: {:?}", line, err);
+            METRICS.unrecognized_line();
             Received::Unrecognized(line)
         }
     }
 }
 
+/// Bridges connect the same two nodes, regardless of direction
+fn same_bridge((a1, b1): &(Node, Node), (a2, b2): &(Node, Node)) -> bool {
+    (a1 == a2 && b1 == b2) || (a1 == b2 && b1 == a2)
+}
+
+/// `bridgelist` with `bridges` added. Bridges that already exist (in either direction) are ignored.
+pub(crate) fn bridges_added(mut bridgelist: Bridgelist, bridges: Bridgelist) -> Bridgelist {
+    for bridge in bridges {
+        if !bridgelist.iter().any(|other| same_bridge(other, &bridge)) {
+            bridgelist.push(bridge);
+        }
+    }
+    bridgelist
+}
+
+/// `bridgelist` without `bridges`, which are matched in either direction
+pub(crate) fn bridges_removed(mut bridgelist: Bridgelist, bridges: &Bridgelist) -> Bridgelist {
+    bridgelist.retain(|bridge| !bridges.iter().any(|other| same_bridge(other, bridge)));
+    bridgelist
+}
+
 /// Instructions are messages sent from the host to the Jumperless
-enum Instruction {
+pub(crate) enum Instruction {
     GetNetlist,
     SetNetlist(Vec<Net>),
     GetBridgelist,
@@ -51,7 +102,23 @@ enum Instruction {
 }
 
 impl Instruction {
-    fn generate(&self, sequence_number: u32) -> String {
+    /// Name of the variant, used to label metrics
+    fn name(&self) -> &'static str {
+        match self {
+            Instruction::GetNetlist => "GetNetlist",
+            Instruction::SetNetlist(_) => "SetNetlist",
+            Instruction::GetBridgelist => "GetBridgelist",
+            Instruction::SetBridgelist(_) => "SetBridgelist",
+            Instruction::GetSupplySwitch => "GetSupplySwitch",
+            Instruction::SetSupplySwitch(_) => "SetSupplySwitch",
+            Instruction::Lightnet(_, _) => "Lightnet",
+            Instruction::GetChipStatus => "GetChipStatus",
+            Instruction::Raw(_, _) => "Raw",
+        }
+    }
+
+    /// Line sent to the device (without line terminator)
+    pub(crate) fn generate(&self, sequence_number: u32) -> String {
         match self {
             Instruction::Raw(instruction, args) => {
                 format!("::{}:{}[{}]", instruction, sequence_number, args)
@@ -124,6 +191,7 @@ impl<L: DeviceLogger> Device<L> {
         let port = serialport::new(port_path.as_str(), 57600)
             .timeout(PORT_TIMEOUT)
             .open()
+            .map_err(|e| Error::NotConnected(e.to_string()))
             .with_context(|| format!("Failed to open serial port: {}", port_path))?;
         logger.open(port_path.as_str());
         let mut device = Self {
@@ -131,6 +199,8 @@ impl<L: DeviceLogger> Device<L> {
             logger,
             reader: None,
             sequence: AtomicU32::new(0),
+            in_flight: None,
+            lights: BTreeMap::new(),
         };
 
         device.start_reader_thread()?;
@@ -146,6 +216,11 @@ impl<L: DeviceLogger> Device<L> {
         !thread.is_finished()
     }
 
+    /// Name of the serial port, if known
+    pub fn port_name(&self) -> Option<String> {
+        self.port.name()
+    }
+
     pub fn raw(&mut self, instruction: String, args: String) -> Result<(bool, Vec<Message>)> {
         let mut messages = vec![];
         self.send_instruction(Instruction::Raw(instruction, args))?;
@@ -154,14 +229,13 @@ impl<L: DeviceLogger> Device<L> {
                 Received::Message(Message::Ok(_)) => break true,
                 Received::Message(Message::Error(_)) => break false,
                 Received::Message(message) => messages.push(message),
-                Received::Error(error) => {
-                    return Err(anyhow::anyhow!("Received an error: {}", error))
-                }
+                Received::Error(error) => return Err(error.into()),
                 Received::Unrecognized(chunk) => {
-                    return Err(anyhow::anyhow!("Received unparsable: {:?}", chunk))
+                    return Err(Error::Protocol(format!("Received unparsable: {:?}", chunk)).into())
                 }
             }
         };
+        self.record_roundtrip();
         Ok((success, messages))
     }
 
@@ -171,6 +245,7 @@ impl<L: DeviceLogger> Device<L> {
         let bridgelist = loop {
             match self.receive() {
                 Received::Message(Message::Bridgelist(bridgelist)) => break bridgelist,
+                Received::Error(error) => return Err(error.into()),
                 other => {
                     eprintln!("WARNING: received sth unexpected: {:?}", other);
                 }
@@ -186,6 +261,24 @@ impl<L: DeviceLogger> Device<L> {
         self.receive_ok(seq)
     }
 
+    /// Add bridges to the current list of bridges
+    ///
+    /// Bridges that already exist (in either direction) are ignored. Returns the resulting list.
+    pub fn add_bridges(&mut self, bridges: Bridgelist) -> Result<Bridgelist> {
+        let combined = bridges_added(self.bridgelist()?, bridges);
+        self.set_bridgelist(combined.clone())?;
+        Ok(combined)
+    }
+
+    /// Remove bridges from the current list of bridges
+    ///
+    /// Bridges are matched in either direction. Returns the resulting list.
+    pub fn remove_bridges(&mut self, bridges: Bridgelist) -> Result<Bridgelist> {
+        let remaining = bridges_removed(self.bridgelist()?, &bridges);
+        self.set_bridgelist(remaining.clone())?;
+        Ok(remaining)
+    }
+
     pub fn receive_ok(&mut self, sequence_number: u32) -> Result<()> {
         self.receive_ok_capture(sequence_number, |_| {})
     }
@@ -198,13 +291,15 @@ impl<L: DeviceLogger> Device<L> {
         loop {
             match self.receive() {
                 Received::Message(Message::Ok(Some(seq))) if seq == sequence_number => {
-                    return Ok(())
+                    self.record_roundtrip();
+                    return Ok(());
                 }
                 Received::Message(Message::Error(Some(seq))) if seq == sequence_number => {
-                    return Err(anyhow::anyhow!("Received error response"))
+                    self.record_roundtrip();
+                    return Err(Error::Protocol("Received error response".to_string()).into());
                 }
                 Received::Message(message) => capture(message),
-                Received::Error(error) => return Err(anyhow::anyhow!("{:?}", error)),
+                Received::Error(error) => return Err(error.into()),
                 _ => {}
             }
         }
@@ -244,7 +339,7 @@ impl<L: DeviceLogger> Device<L> {
                 result = Some(pos);
             }
         })?;
-        result.ok_or(anyhow::anyhow!("No ::supplyswitch message received!"))
+        result.ok_or(Error::Protocol("No ::supplyswitch message received!".to_string()).into())
     }
 
     pub fn set_supply_switch(&mut self, pos: SupplySwitchPos) -> Result<()> {
@@ -274,31 +369,47 @@ impl<L: DeviceLogger> Device<L> {
     }
 
     pub fn lightnet(&mut self, name: String, color: Color) -> Result<()> {
-        self.send_instruction(Instruction::Lightnet(name, color))?;
+        self.send_instruction(Instruction::Lightnet(name.clone(), color))?;
+        self.lights.insert(name, color);
         Ok(())
     }
 
+    /// Colors of the lights set since the device was opened, by light name
+    pub fn lights(&self) -> &BTreeMap<String, Color> {
+        &self.lights
+    }
+
     fn send_instruction(&mut self, instruction: Instruction) -> Result<u32> {
         let sequence_number = self.sequence.fetch_add(1, Ordering::SeqCst) + 1;
         let msg = instruction.generate(sequence_number);
         self.logger.sent(&msg);
-        write!(self.port, "{}\r\n", msg)?;
+        write!(self.port, "{}\r\n", msg).map_err(|e| Error::NotConnected(e.to_string()))?;
+        self.in_flight = Some((instruction.name(), Instant::now()));
         Ok(sequence_number)
     }
 
+    fn record_roundtrip(&mut self) {
+        if let Some((instruction, sent)) = self.in_flight.take() {
+            METRICS.device_roundtrip(instruction, sent.elapsed());
+        }
+    }
+
     fn receive(&mut self) -> Received {
         let (_, recv, _) = self.reader.as_mut().expect("Reader thread");
         match recv.recv_timeout(RESPONSE_TIMEOUT) {
             Ok(received) => received,
-            _ => Received::Error("Timeout while receiving reply".to_string()),
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some((instruction, _)) = self.in_flight.take() {
+                    METRICS.device_timeout(instruction);
+                }
+                Received::Error(Error::Timeout)
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Received::Error(Error::NotConnected("Reader thread terminated".to_string()))
+            }
         }
     }
 
-    pub fn clear_nodefile(&mut self) -> Result<()> {
-        self.port.write_all(b"f{\n}\n")?;
-        Ok(())
-    }
-
     fn start_reader_thread(&mut self) -> Result<()> {
         let port = self.port.try_clone()?;
         let logger = self.logger.clone();
@@ -345,10 +456,10 @@ impl<L: DeviceLogger> Device<L> {
                     } else {
                         eprintln!("ERROR: {:?}", err);
                         sender
-                            .send(Received::Error(format!(
+                            .send(Received::Error(Error::NotConnected(format!(
                                 "Read from serial port failed: {:?}",
                                 err
-                            )))
+                            ))))
                             .unwrap();
 
                         // terminate thread
@@ -1,7 +1,11 @@
-use crate::{device::Device, logger::DeviceLogger};
+use crate::{
+    device::{self, Device},
+    logger::DeviceLogger,
+    metrics::METRICS,
+};
 use anyhow::{Context, Result};
 use log::{debug, error};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
 use std::collections::HashMap;
 
@@ -10,11 +14,14 @@ pub struct DeviceManager<L: DeviceLogger> {
     path: Option<String>,
     device: Option<Device<L>>,
     logger: L,
+    /// Set once a device was opened, to tell reconnects from the initial connection
+    was_connected: bool,
 }
 
-#[derive(Serialize)]
+#[cfg(feature = "server")]
+#[derive(Serialize, utoipa::ToSchema)]
 pub struct Status {
-    connected: bool,
+    pub connected: bool,
 }
 
 impl<L: DeviceLogger> DeviceManager<L> {
@@ -38,9 +45,24 @@ impl<L: DeviceLogger> DeviceManager<L> {
             path,
             device: None,
             logger,
+            was_connected: false,
         }
     }
 
+    /// Change the serial port to use.
+    ///
+    /// Closes the current device (if any). Passing `None` enables dynamic port detection.
+    #[cfg(feature = "server")]
+    pub fn set_path(&mut self, path: Option<String>) {
+        match &path {
+            Some(path) => debug!("Switching to fixed port {}", path),
+            None => debug!("Switching to dynamic port detection"),
+        }
+        self.close_device();
+        self.path = path;
+    }
+
+    #[cfg(feature = "server")]
     pub fn status(&mut self) -> Result<Status> {
         let connected = self.with_device(|_| Ok(())).is_ok();
         Ok(Status { connected })
@@ -53,13 +75,22 @@ impl<L: DeviceLogger> DeviceManager<L> {
         f(self.device()?).map_err(|e| self.forget_device(e))
     }
 
+    /// Name of the serial port, if the device is currently open
+    pub fn connected_port(&self) -> Option<String> {
+        self.device
+            .as_ref()
+            .filter(|device| device.is_alive())
+            .and_then(|device| device.port_name())
+    }
+
     pub fn close_device(&mut self) {
         self.device = None;
+        METRICS.set_connected(false);
     }
 
     fn forget_device(&mut self, error: anyhow::Error) -> anyhow::Error {
         log::error!("Error communicating with device: {}", error);
-        self.device = None;
+        self.close_device();
         error
     }
 
@@ -73,10 +104,23 @@ impl<L: DeviceLogger> DeviceManager<L> {
     }
 
     fn open(&mut self) -> Result<&mut Device<L>> {
-        let port_path = self.port_path()?;
-        let device = Device::new(port_path.clone(), self.logger.clone())?;
+        let opened = self.port_path().and_then(|port_path| {
+            Device::new(port_path.clone(), self.logger.clone()).map(|device| (port_path, device))
+        });
+        let (port_path, device) = match opened {
+            Ok(opened) => opened,
+            Err(error) => {
+                METRICS.set_connected(false);
+                return Err(error);
+            }
+        };
         self.device = Some(device);
         log::info!("Connected to jumperless on port {}", port_path);
+        if self.was_connected {
+            METRICS.reconnect();
+        }
+        self.was_connected = true;
+        METRICS.set_connected(true);
         Ok(self.device.as_mut().unwrap())
     }
 
@@ -89,7 +133,9 @@ impl<L: DeviceLogger> DeviceManager<L> {
             .list_ports()?
             .into_iter()
             .find(|port| port.role == PortRole::JumperlessPrimary)
-            .ok_or(anyhow::anyhow!("No matching serial port found"))?;
+            .ok_or(device::Error::NotConnected(
+                "No matching serial port found".to_string(),
+            ))?;
 
         debug!("Found primary: {:?}", primary.info);
 
@@ -182,9 +228,11 @@ impl<L: DeviceLogger> DeviceManager<L> {
 }
 
 /// A serial port that was found by [`DeviceManager::list_ports`]
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
 pub struct FoundPort {
     /// The original port info
+    #[cfg_attr(feature = "server", schema(value_type = Object))]
     pub info: SerialPortInfo,
     /// Identified role
     pub role: PortRole,
@@ -201,7 +249,8 @@ impl FoundPort {
 }
 
 /// A role, used in [FoundPort]
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
 pub enum PortRole {
     /// No idea what this device is
     Unknown,
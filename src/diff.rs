@@ -0,0 +1,393 @@
+//! Differences between two states of the board.
+
+use crate::{
+    parser,
+    state::BoardState,
+    types::{Bridgelist, Color, Net, Node, SupplySwitchPos},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A single difference, describing how to get from the old state to the new one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    NetAdded {
+        index: u8,
+        name: String,
+        nodes: Vec<Node>,
+    },
+    NetRemoved {
+        index: u8,
+        name: String,
+        nodes: Vec<Node>,
+    },
+    /// The net exists in both states, but gained nodes
+    NodesAdded {
+        index: u8,
+        name: String,
+        nodes: Vec<Node>,
+    },
+    /// The net exists in both states, but lost nodes
+    NodesRemoved {
+        index: u8,
+        name: String,
+        nodes: Vec<Node>,
+    },
+    NetRenamed {
+        index: u8,
+        from: String,
+        to: String,
+    },
+    NetRecolored {
+        index: u8,
+        name: String,
+        from: Color,
+        to: Color,
+    },
+    BridgeAdded {
+        from: Node,
+        to: Node,
+    },
+    BridgeRemoved {
+        from: Node,
+        to: Node,
+    },
+    SupplySwitchChanged {
+        from: SupplySwitchPos,
+        to: SupplySwitchPos,
+    },
+    /// A light was set, changed or is no longer set
+    LightChanged {
+        name: String,
+        from: Option<Color>,
+        to: Option<Color>,
+    },
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::NetAdded { index, name, nodes } => {
+                write!(f, "+ net {} {:?}: {}", index, name, format_nodes(nodes))
+            }
+            Change::NetRemoved { index, name, nodes } => {
+                write!(f, "- net {} {:?}: {}", index, name, format_nodes(nodes))
+            }
+            Change::NodesAdded { index, name, nodes } => {
+                write!(f, "~ net {} {:?}: + {}", index, name, format_nodes(nodes))
+            }
+            Change::NodesRemoved { index, name, nodes } => {
+                write!(f, "~ net {} {:?}: - {}", index, name, format_nodes(nodes))
+            }
+            Change::NetRenamed { index, from, to } => {
+                write!(f, "~ net {}: renamed {:?} -> {:?}", index, from, to)
+            }
+            Change::NetRecolored {
+                index,
+                name,
+                from,
+                to,
+            } => write!(f, "~ net {} {:?}: color {} -> {}", index, name, from, to),
+            Change::BridgeAdded { from, to } => write!(f, "+ bridge {}-{}", from, to),
+            Change::BridgeRemoved { from, to } => write!(f, "- bridge {}-{}", from, to),
+            Change::SupplySwitchChanged { from, to } => {
+                write!(f, "~ supply switch: {} -> {}", from, to)
+            }
+            Change::LightChanged { name, from, to } => {
+                write!(
+                    f,
+                    "~ light {}: {} -> {}",
+                    name,
+                    format_color(from),
+                    format_color(to)
+                )
+            }
+        }
+    }
+}
+
+fn format_nodes(nodes: &[Node]) -> String {
+    nodes
+        .iter()
+        .map(|node| node.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_color(color: &Option<Color>) -> String {
+    match color {
+        Some(color) => color.to_string(),
+        None => "(unset)".to_string(),
+    }
+}
+
+/// A board state, of which only some parts may be known (e.g. when read from a file that only contains bridges)
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct PartialState {
+    #[serde(default)]
+    pub netlist: Option<Vec<Net>>,
+    #[serde(default)]
+    pub bridgelist: Option<Bridgelist>,
+    #[serde(default)]
+    pub supply_switch: Option<SupplySwitchPos>,
+}
+
+impl PartialState {
+    /// Parse the contents of a file, which is one of:
+    /// - a JSON object with any of `netlist`, `bridgelist` and `supply_switch` (e.g. a snapshot)
+    /// - a JSON array of nets (as written by `net list -o json`)
+    /// - a list of bridges (as accepted by `bridge set`)
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        if input.starts_with('{') {
+            return Ok(serde_json::from_str(input)?);
+        }
+        if input.starts_with('[') {
+            if let Ok(netlist) = serde_json::from_str(input) {
+                return Ok(Self {
+                    netlist: Some(netlist),
+                    ..Default::default()
+                });
+            }
+        }
+        Ok(Self {
+            bridgelist: Some(parser::bridgelist_input(input)?),
+            ..Default::default()
+        })
+    }
+
+    /// Whether both states include at least one of the same parts, so [`partial`] has something to compare
+    pub fn overlaps(&self, other: &PartialState) -> bool {
+        (self.netlist.is_some() && other.netlist.is_some())
+            || (self.bridgelist.is_some() && other.bridgelist.is_some())
+            || (self.supply_switch.is_some() && other.supply_switch.is_some())
+    }
+}
+
+impl From<BoardState> for PartialState {
+    fn from(state: BoardState) -> Self {
+        Self {
+            netlist: Some(state.netlist),
+            bridgelist: Some(state.bridgelist),
+            supply_switch: Some(state.supply_switch),
+        }
+    }
+}
+
+/// Compute the changes needed to get from `old` to `new`, for the parts that are known in both
+pub fn partial(old: &PartialState, new: &PartialState) -> Vec<Change> {
+    let mut changes = vec![];
+    if let (Some(old), Some(new)) = (&old.netlist, &new.netlist) {
+        changes.extend(netlists(old, new));
+    }
+    if let (Some(old), Some(new)) = (&old.bridgelist, &new.bridgelist) {
+        changes.extend(bridgelists(old, new));
+    }
+    if let (Some(old), Some(new)) = (old.supply_switch, new.supply_switch) {
+        if old != new {
+            changes.push(Change::SupplySwitchChanged { from: old, to: new });
+        }
+    }
+    changes
+}
+
+/// Compute the changes needed to get from `old` to `new`
+pub fn states(old: &BoardState, new: &BoardState) -> Vec<Change> {
+    let mut changes = netlists(&old.netlist, &new.netlist);
+    changes.extend(bridgelists(&old.bridgelist, &new.bridgelist));
+    if old.supply_switch != new.supply_switch {
+        changes.push(Change::SupplySwitchChanged {
+            from: old.supply_switch,
+            to: new.supply_switch,
+        });
+    }
+    changes
+}
+
+/// Compare nets by index. Order of nodes within a net does not matter.
+pub fn netlists(old: &[Net], new: &[Net]) -> Vec<Change> {
+    let mut changes = vec![];
+    for net in old {
+        if !new.iter().any(|other| other.index == net.index) {
+            changes.push(Change::NetRemoved {
+                index: net.index,
+                name: net.name.clone(),
+                nodes: net.nodes.clone(),
+            });
+        }
+    }
+    for net in new {
+        let Some(old_net) = old.iter().find(|other| other.index == net.index) else {
+            changes.push(Change::NetAdded {
+                index: net.index,
+                name: net.name.clone(),
+                nodes: net.nodes.clone(),
+            });
+            continue;
+        };
+        if old_net.name != net.name {
+            changes.push(Change::NetRenamed {
+                index: net.index,
+                from: old_net.name.clone(),
+                to: net.name.clone(),
+            });
+        }
+        if old_net.color != net.color {
+            changes.push(Change::NetRecolored {
+                index: net.index,
+                name: net.name.clone(),
+                from: old_net.color,
+                to: net.color,
+            });
+        }
+        let added = missing_from(&net.nodes, &old_net.nodes);
+        if !added.is_empty() {
+            changes.push(Change::NodesAdded {
+                index: net.index,
+                name: net.name.clone(),
+                nodes: added,
+            });
+        }
+        let removed = missing_from(&old_net.nodes, &net.nodes);
+        if !removed.is_empty() {
+            changes.push(Change::NodesRemoved {
+                index: net.index,
+                name: net.name.clone(),
+                nodes: removed,
+            });
+        }
+    }
+    changes
+}
+
+/// Compare bridges, regardless of the order of their ends
+pub fn bridgelists(old: &[(Node, Node)], new: &[(Node, Node)]) -> Vec<Change> {
+    let same = |a: &(Node, Node), b: &(Node, Node)| *a == *b || (a.0 == b.1 && a.1 == b.0);
+    let removed = old
+        .iter()
+        .filter(|bridge| !new.iter().any(|other| same(bridge, other)))
+        .map(|&(from, to)| Change::BridgeRemoved { from, to });
+    let added = new
+        .iter()
+        .filter(|bridge| !old.iter().any(|other| same(bridge, other)))
+        .map(|&(from, to)| Change::BridgeAdded { from, to });
+    removed.chain(added).collect()
+}
+
+pub fn lights(old: &BTreeMap<String, Color>, new: &BTreeMap<String, Color>) -> Vec<Change> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| Change::LightChanged {
+            name: name.clone(),
+            from: old.get(name).copied(),
+            to: new.get(name).copied(),
+        })
+        .collect()
+}
+
+/// Nodes in `nodes`, which are not in `other`
+fn missing_from(nodes: &[Node], other: &[Node]) -> Vec<Node> {
+    nodes
+        .iter()
+        .filter(|node| !other.contains(node))
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(index: u8, name: &str, nodes: Vec<Node>) -> Net {
+        Net {
+            index,
+            number: index,
+            nodes,
+            special: false,
+            color: Color([0, 0, 0]),
+            machine: false,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_states() {
+        let old = BoardState {
+            netlist: vec![
+                net(1, "GND", vec![Node::GND]),
+                net(8, "Net 8", vec![Node::Column(3), Node::Column(7)]),
+                net(9, "Net 9", vec![Node::Column(10), Node::Column(11)]),
+            ],
+            bridgelist: vec![
+                (Node::Column(3), Node::Column(7)),
+                (Node::Column(10), Node::Column(11)),
+            ],
+            supply_switch: SupplySwitchPos::V5,
+        };
+        let mut new = BoardState {
+            netlist: vec![
+                net(1, "GND", vec![Node::GND]),
+                net(8, "LED", vec![Node::Column(7), Node::Column(3), Node::GND]),
+            ],
+            bridgelist: vec![
+                (Node::Column(7), Node::Column(3)),
+                (Node::Column(3), Node::GND),
+            ],
+            supply_switch: SupplySwitchPos::V8,
+        };
+        new.netlist[1].color = Color([255, 0, 0]);
+
+        assert_eq!(
+            states(&old, &new)
+                .iter()
+                .map(|change| change.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "- net 9 \"Net 9\": 10, 11",
+                "~ net 8: renamed \"Net 8\" -> \"LED\"",
+                "~ net 8 \"LED\": color #000000 -> #ff0000",
+                "~ net 8 \"LED\": + GND",
+                "- bridge 10-11",
+                "+ bridge 3-GND",
+                "~ supply switch: 5V -> 8V",
+            ]
+        );
+        assert!(states(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        assert_eq!(
+            PartialState::parse("3-7,GND-17\n").unwrap().bridgelist,
+            Some(vec![
+                (Node::Column(3), Node::Column(7)),
+                (Node::GND, Node::Column(17))
+            ])
+        );
+        assert_eq!(
+            PartialState::parse(r#"[["GND", 17]]"#).unwrap().bridgelist,
+            Some(vec![(Node::GND, Node::Column(17))])
+        );
+        let state = PartialState::parse(r#"{"supply_switch": "8V"}"#).unwrap();
+        assert_eq!(state.supply_switch, Some(SupplySwitchPos::V8));
+        assert!(state.netlist.is_none());
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let bridges = PartialState::parse("3-7").unwrap();
+        let netlist = PartialState::parse("[]").unwrap();
+        let switch = PartialState::parse(r#"{"supply_switch": "8V", "netlist": []}"#).unwrap();
+        assert!(!bridges.overlaps(&netlist));
+        assert!(!bridges.overlaps(&switch));
+        assert!(netlist.overlaps(&switch));
+        assert!(bridges.overlaps(&bridges));
+    }
+}
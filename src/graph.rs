@@ -0,0 +1,188 @@
+//! Export of the connectivity graph, for documentation and review: Graphviz DOT and GraphML.
+//!
+//! Vertices are the nodes that are part of a net or bridge, labeled by their name. Each bridge is an edge,
+//! and each net is a cluster (a subgraph in DOT, a nested graph in GraphML), drawn in the net's color.
+
+use crate::{
+    render::xml_escape,
+    types::{Color, Net, Node},
+};
+
+/// Nodes that are part of a bridge, but not of any net
+fn loose_nodes(netlist: &[Net], bridgelist: &[(Node, Node)]) -> Vec<Node> {
+    let mut loose = vec![];
+    for node in bridgelist.iter().flat_map(|(a, b)| [*a, *b]) {
+        if !loose.contains(&node) && !netlist.iter().any(|net| net.nodes.contains(&node)) {
+            loose.push(node);
+        }
+    }
+    loose
+}
+
+fn net_label(net: &Net) -> String {
+    format!("{} {}", net.number, net.name)
+}
+
+fn text_color(color: Color) -> Color {
+    if color.is_light() {
+        Color([0, 0, 0])
+    } else {
+        Color([255, 255, 255])
+    }
+}
+
+/// Graphviz DOT, e.g. for `dot -Tsvg`
+pub fn to_dot(netlist: &[Net], bridgelist: &[(Node, Node)]) -> String {
+    let mut dot = String::from("graph jumperless {\n");
+    dot.push_str(
+        "    node [shape=box, style=\"rounded,filled\", fillcolor=\"#ffffff\", fontname=\"monospace\"];\n",
+    );
+    for net in netlist {
+        dot.push_str(&format!("\n    subgraph cluster_net_{} {{\n", net.index));
+        dot.push_str(&format!("        label={};\n", dot_string(&net_label(net))));
+        dot.push_str(&format!("        color=\"{}\";\n", net.color));
+        dot.push_str("        penwidth=2;\n");
+        for node in &net.nodes {
+            dot.push_str(&format!(
+                "        {} [fillcolor=\"{}\", fontcolor=\"{}\"];\n",
+                dot_string(&node.to_string()),
+                net.color,
+                text_color(net.color)
+            ));
+        }
+        dot.push_str("    }\n");
+    }
+    let loose = loose_nodes(netlist, bridgelist);
+    if !loose.is_empty() {
+        dot.push('\n');
+        for node in loose {
+            dot.push_str(&format!("    {};\n", dot_string(&node.to_string())));
+        }
+    }
+    if !bridgelist.is_empty() {
+        dot.push('\n');
+        for (a, b) in bridgelist {
+            let color = netlist
+                .iter()
+                .find(|net| net.nodes.contains(a))
+                .map(|net| format!(" [color=\"{}\", penwidth=2]", net.color))
+                .unwrap_or_default();
+            dot.push_str(&format!(
+                "    {} -- {}{};\n",
+                dot_string(&a.to_string()),
+                dot_string(&b.to_string()),
+                color
+            ));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn dot_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// GraphML, e.g. for yEd or Gephi. Nets are nodes with a nested graph, which contains the net's nodes.
+pub fn to_graphml(netlist: &[Net], bridgelist: &[(Node, Node)]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    xml.push_str("  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n");
+    xml.push_str("  <key id=\"color\" for=\"all\" attr.name=\"color\" attr.type=\"string\"/>\n");
+    xml.push_str(
+        "  <key id=\"special\" for=\"node\" attr.name=\"special\" attr.type=\"boolean\"/>\n",
+    );
+    xml.push_str("  <graph id=\"jumperless\" edgedefault=\"undirected\">\n");
+    let node = |node: &Node, color: Option<Color>, indent: &str| {
+        let name = xml_escape(&node.to_string());
+        let mut element = format!(
+            "{}<node id=\"{}\"><data key=\"label\">{}</data>",
+            indent, name, name
+        );
+        if let Some(color) = color {
+            element.push_str(&format!("<data key=\"color\">{}</data>", color));
+        }
+        element.push_str("</node>\n");
+        element
+    };
+    for net in netlist {
+        xml.push_str(&format!("    <node id=\"net:{}\">\n", net.index));
+        xml.push_str(&format!(
+            "      <data key=\"label\">{}</data>\n",
+            xml_escape(&net_label(net))
+        ));
+        xml.push_str(&format!("      <data key=\"color\">{}</data>\n", net.color));
+        xml.push_str(&format!(
+            "      <data key=\"special\">{}</data>\n",
+            net.special
+        ));
+        xml.push_str(&format!(
+            "      <graph id=\"net:{}:\" edgedefault=\"undirected\">\n",
+            net.index
+        ));
+        for n in &net.nodes {
+            xml.push_str(&node(n, Some(net.color), "        "));
+        }
+        xml.push_str("      </graph>\n    </node>\n");
+    }
+    for n in loose_nodes(netlist, bridgelist) {
+        xml.push_str(&node(&n, None, "    "));
+    }
+    for (a, b) in bridgelist {
+        xml.push_str(&format!(
+            "    <edge source=\"{}\" target=\"{}\"/>\n",
+            xml_escape(&a.to_string()),
+            xml_escape(&b.to_string())
+        ));
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot() {
+        let netlist = vec![Net {
+            index: 8,
+            number: 8,
+            nodes: vec![Node::Column(3), Node::NANO_D13],
+            special: false,
+            color: Color([255, 0, 0]),
+            machine: false,
+            name: "LED \"red\"".to_string(),
+        }];
+        let bridgelist = vec![
+            (Node::Column(3), Node::NANO_D13),
+            (Node::Column(10), Node::GND),
+        ];
+        assert_eq!(
+            to_dot(&netlist, &bridgelist),
+            r##"graph jumperless {
+    node [shape=box, style="rounded,filled", fillcolor="#ffffff", fontname="monospace"];
+
+    subgraph cluster_net_8 {
+        label="8 LED \"red\"";
+        color="#ff0000";
+        penwidth=2;
+        "3" [fillcolor="#ff0000", fontcolor="#ffffff"];
+        "NANO_D13" [fillcolor="#ff0000", fontcolor="#ffffff"];
+    }
+
+    "10";
+    "GND";
+
+    "3" -- "NANO_D13" [color="#ff0000", penwidth=2];
+    "10" -- "GND";
+}
+"##
+        );
+
+        let graphml = to_graphml(&netlist, &bridgelist);
+        assert!(graphml.contains("<data key=\"label\">8 LED &quot;red&quot;</data>"));
+        assert!(graphml.contains("<node id=\"GND\"><data key=\"label\">GND</data></node>"));
+        assert!(graphml.contains("<edge source=\"10\" target=\"GND\"/>"));
+    }
+}
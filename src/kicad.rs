@@ -0,0 +1,492 @@
+//! Import of KiCad netlists (`.net` files, as exported by Eeschema).
+//!
+//! KiCad nets connect component pins, which have no place on the Jumperless by themselves. A placement file
+//! says where each pin sits: on a breadboard column, or on one of the named nodes (e.g. a Nano pin):
+//!
+//! ```text
+//! # <reference>.<pin> = <node>
+//! R1.1 = 17
+//! D1.2 = NANO_D13
+//! # whole nets can be placed too, e.g. to connect them to a supply rail
+//! net:VBAT = SUPPLY_5V
+//! ```
+//!
+//! Nets named `GND`, `+5V`, `+3V3` or `+3.3V` are connected to the matching rail, unless placed otherwise.
+
+use crate::{
+    layout,
+    types::{Bridgelist, Net, NewNet, Node},
+    validate,
+};
+use anyhow::{Context, Result};
+use nom::{
+    branch::alt,
+    bytes::complete::{escaped_transform, is_not, tag, take_while1},
+    character::complete::{char, multispace0},
+    combinator::{all_consuming, map, value},
+    multi::many0,
+    sequence::{delimited, preceded, terminated},
+    IResult,
+};
+use std::collections::BTreeMap;
+
+/// A pin of a component, e.g. `R1.2`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pin {
+    pub reference: String,
+    pub pin: String,
+}
+
+impl std::fmt::Display for Pin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.reference, self.pin)
+    }
+}
+
+/// A net of the KiCad netlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct KicadNet {
+    pub name: String,
+    pub pins: Vec<Pin>,
+}
+
+/// S-expression, as used by KiCad files
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Atom(String),
+    List(Vec<Expr>),
+}
+
+impl Expr {
+    /// For a list like `(name ...)`, the name
+    fn name(&self) -> Option<&str> {
+        match self {
+            Expr::List(items) => match items.first() {
+                Some(Expr::Atom(name)) => Some(name),
+                _ => None,
+            },
+            Expr::Atom(_) => None,
+        }
+    }
+
+    /// Items after the name, that are lists called `name`
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Expr> {
+        let items = match self {
+            Expr::List(items) => &items[1.min(items.len())..],
+            Expr::Atom(_) => &[],
+        };
+        items.iter().filter(move |item| item.name() == Some(name))
+    }
+
+    /// Value of a property like `(name "value")`
+    fn property<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        match self.children(name).next() {
+            Some(Expr::List(items)) => match items.get(1) {
+                Some(Expr::Atom(value)) => Some(value),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn expr(input: &str) -> IResult<&str, Expr> {
+    delimited(
+        multispace0,
+        alt((
+            map(
+                delimited(char('('), many0(expr), preceded(multispace0, char(')'))),
+                Expr::List,
+            ),
+            map(quoted, Expr::Atom),
+            map(
+                take_while1(|c: char| !c.is_whitespace() && c != '(' && c != ')' && c != '"'),
+                |atom: &str| Expr::Atom(atom.to_string()),
+            ),
+        )),
+        multispace0,
+    )(input)
+}
+
+fn quoted(input: &str) -> IResult<&str, String> {
+    alt((
+        value(String::new(), tag("\"\"")),
+        delimited(
+            char('"'),
+            escaped_transform(
+                is_not("\\\""),
+                '\\',
+                alt((
+                    value("\\", char('\\')),
+                    value("\"", char('"')),
+                    value("\n", char('n')),
+                )),
+            ),
+            char('"'),
+        ),
+    ))(input)
+}
+
+/// Parse the nets of a KiCad netlist (s-expression format, KiCad 5 or later)
+pub fn parse_netlist(input: &str) -> Result<Vec<KicadNet>> {
+    let (_, export) = all_consuming(terminated(expr, multispace0))(input).map_err(|e| {
+        let line = match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => {
+                input[..input.len() - e.input.len()].lines().count().max(1)
+            }
+            nom::Err::Incomplete(_) => input.lines().count(),
+        };
+        anyhow::anyhow!("Failed to parse KiCad netlist, near line {}", line)
+    })?;
+    if export.name() != Some("export") {
+        return Err(anyhow::anyhow!(
+            "Not a KiCad netlist: expected (export ...), in s-expression format"
+        ));
+    }
+    let nets = export
+        .children("nets")
+        .flat_map(|nets| nets.children("net"))
+        .map(|net| KicadNet {
+            // top-level sheet names start with a slash
+            name: net
+                .property("name")
+                .unwrap_or_default()
+                .trim_start_matches('/')
+                .to_string(),
+            pins: net
+                .children("node")
+                .map(|node| Pin {
+                    reference: node.property("ref").unwrap_or_default().to_string(),
+                    pin: node.property("pin").unwrap_or_default().to_string(),
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(nets)
+}
+
+/// Where pins and nets go on the board, as read from a placement file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Placement {
+    /// By pin, written as `<reference>.<pin>`
+    pub pins: BTreeMap<String, Node>,
+    /// By KiCad net name
+    pub nets: BTreeMap<String, Node>,
+}
+
+impl Placement {
+    /// Parse a placement file: one `<reference>.<pin> = <node>` or `net:<name> = <node>` per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut placement = Self::default();
+        for (number, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, node) = line
+                .split_once('=')
+                .with_context(|| format!("Line {}: expected <pin> = <node>", number + 1))?;
+            let node = Node::parse(node.trim())
+                .with_context(|| format!("Line {}: invalid node {:?}", number + 1, node.trim()))?;
+            let key = key.trim();
+            match key.strip_prefix("net:") {
+                Some(net) => placement.nets.insert(net.trim().to_string(), node),
+                None => placement.pins.insert(key.to_string(), node),
+            };
+        }
+        Ok(placement)
+    }
+}
+
+/// Something in the KiCad netlist that could not be carried over to the Jumperless
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// A pin that is connected to other pins, but not placed
+    UnmappedPin { net: String, pin: Pin },
+    /// Pins of different nets are placed on the same node, which connects the nets
+    Shorted { node: Node, nets: Vec<String> },
+    /// The net would connect two special nets (e.g. GND and +5V), so it is left out
+    SpecialNets { nets: Vec<String>, nodes: Vec<Node> },
+    /// The placement file places a pin or net that is not in the netlist
+    UnknownPlacement { name: String },
+    /// All net indices are taken, so the net is left out
+    NoFreeIndex { net: String },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |nodes: &[Node]| {
+            nodes
+                .iter()
+                .map(|node| node.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Problem::UnmappedPin { net, pin } => {
+                write!(f, "Pin {} of net {:?} is not placed", pin, net)
+            }
+            Problem::Shorted { node, nets } => write!(
+                f,
+                "Nets {} are connected, because they share node {}",
+                nets.join(", "),
+                node
+            ),
+            Problem::SpecialNets { nets, nodes } => write!(
+                f,
+                "Net {} would connect special nodes {}, left out",
+                nets.join(", "),
+                join(nodes)
+            ),
+            Problem::UnknownPlacement { name } => {
+                write!(f, "{} is placed, but not in the netlist", name)
+            }
+            Problem::NoFreeIndex { net } => {
+                write!(f, "Net {:?} left out, no free net index", net)
+            }
+        }
+    }
+}
+
+/// Result of [`import`]
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// The special nets of the base netlist, plus one net for each KiCad net that connects two or more nodes
+    pub netlist: Vec<Net>,
+    /// Bridges that make the same connections as the netlist
+    pub bridgelist: Bridgelist,
+    pub problems: Vec<Problem>,
+}
+
+/// Rails that KiCad power nets are connected to, unless they are placed
+const POWER_NETS: [(&str, Node); 4] = [
+    ("GND", Node::GND),
+    ("+5V", Node::SUPPLY_5V),
+    ("+3V3", Node::SUPPLY_3V3),
+    ("+3.3V", Node::SUPPLY_3V3),
+];
+
+/// Map the KiCad nets onto the board, as given by the placement.
+///
+/// The special nets are taken from `base` (usually the board's netlist), so their colors stay as they are.
+pub fn import(nets: &[KicadNet], placement: &Placement, base: &[Net]) -> Result<Import> {
+    let mut problems = vec![];
+
+    // nodes of each KiCad net
+    let mut placed: Vec<(Vec<String>, Vec<Node>)> = vec![];
+    for net in nets {
+        let mut nodes = vec![];
+        let rail = placement.nets.get(&net.name).copied().or_else(|| {
+            POWER_NETS
+                .iter()
+                .find(|(name, _)| *name == net.name)
+                .map(|(_, node)| *node)
+        });
+        nodes.extend(rail);
+        for pin in &net.pins {
+            match placement.pins.get(&pin.to_string()) {
+                Some(node) if nodes.contains(node) => {}
+                Some(node) => nodes.push(*node),
+                // pins that are not connected to anything need no place
+                None if net.pins.len() > 1 || rail.is_some() => {
+                    problems.push(Problem::UnmappedPin {
+                        net: net.name.clone(),
+                        pin: pin.clone(),
+                    })
+                }
+                None => {}
+            }
+        }
+        placed.push((vec![net.name.clone()], nodes));
+    }
+
+    for name in placement.pins.keys() {
+        if !nets
+            .iter()
+            .any(|net| net.pins.iter().any(|pin| pin.to_string() == *name))
+        {
+            problems.push(Problem::UnknownPlacement { name: name.clone() });
+        }
+    }
+    for name in placement.nets.keys() {
+        if !nets.iter().any(|net| net.name == *name) {
+            problems.push(Problem::UnknownPlacement {
+                name: format!("net:{}", name),
+            });
+        }
+    }
+
+    // nets that share a node are connected on the board, so they become one
+    let mut merged: Vec<(Vec<String>, Vec<Node>)> = vec![];
+    for (names, nodes) in placed {
+        let mut group = (names, nodes);
+        while let Some(position) = merged
+            .iter()
+            .position(|(_, other)| other.iter().any(|node| group.1.contains(node)))
+        {
+            let (other_names, other_nodes) = merged.remove(position);
+            for node in &other_nodes {
+                if group.1.contains(node) {
+                    problems.push(Problem::Shorted {
+                        node: *node,
+                        nets: other_names.iter().chain(&group.0).cloned().collect(),
+                    });
+                }
+            }
+            group.0.splice(0..0, other_names);
+            for node in other_nodes {
+                if !group.1.contains(&node) {
+                    group.1.push(node);
+                }
+            }
+        }
+        merged.push(group);
+    }
+
+    let mut netlist = validate::special_nets(base);
+    let mut colors = layout::PALETTE.iter().cycle();
+    for (names, nodes) in merged {
+        let special: Vec<usize> = netlist
+            .iter()
+            .enumerate()
+            .filter(|(_, net)| net.special && net.nodes.iter().any(|n| nodes.contains(n)))
+            .map(|(i, _)| i)
+            .collect();
+        match special[..] {
+            [] if nodes.len() > 1 => {
+                let name = names.join("/");
+                let added = NewNet {
+                    nodes,
+                    color: *colors.next().unwrap(),
+                    name: Some(name.clone()),
+                }
+                .add_to(&mut netlist);
+                if added.is_none() {
+                    problems.push(Problem::NoFreeIndex { net: name });
+                }
+            }
+            [] => {}
+            [i] => {
+                for node in nodes {
+                    if !netlist[i].nodes.contains(&node) {
+                        netlist[i].nodes.push(node);
+                    }
+                }
+            }
+            _ => problems.push(Problem::SpecialNets {
+                nets: names,
+                nodes: special.iter().map(|i| netlist[*i].nodes[0]).collect(),
+            }),
+        }
+    }
+    let netlist = validate::netlist(netlist)?;
+
+    // connect the nodes of each net to its first node
+    let bridgelist = netlist
+        .iter()
+        .flat_map(|net| net.nodes.iter().skip(1).map(|node| (net.nodes[0], *node)))
+        .collect();
+
+    Ok(Import {
+        netlist,
+        bridgelist,
+        problems,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Color;
+
+    const NETLIST: &str = r#"(export (version "E")
+  (design (source "blinky.kicad_sch") (tool "Eeschema 8.0.1"))
+  (components
+    (comp (ref "D1") (value "LED"))
+    (comp (ref "R1") (value "220")))
+  (nets
+    (net (code "1") (name "GND") (class "Default")
+      (node (ref "D1") (pin "1") (pinfunction "K") (pintype "passive")))
+    (net (code "2") (name "/LED") (class "Default")
+      (node (ref "R1") (pin "2") (pintype "passive"))
+      (node (ref "D1") (pin "2") (pinfunction "A") (pintype "passive")))
+    (net (code "3") (name "Net-(R1-Pad1)")
+      (node (ref "R1") (pin "1") (pintype "passive"))
+      (node (ref "U1") (pin "16") (pintype "bidirectional")))
+    (net (code "4") (name "unconnected-(U1-D12)")
+      (node (ref "U1") (pin "15") (pintype "bidirectional")))))
+"#;
+
+    #[test]
+    fn test_parse_netlist() {
+        let nets = parse_netlist(NETLIST).unwrap();
+        assert_eq!(nets.len(), 4);
+        assert_eq!(nets[1].name, "LED");
+        assert_eq!(
+            nets[1].pins,
+            vec![
+                Pin {
+                    reference: "R1".to_string(),
+                    pin: "2".to_string()
+                },
+                Pin {
+                    reference: "D1".to_string(),
+                    pin: "2".to_string()
+                },
+            ]
+        );
+
+        // KiCad 5 writes most atoms without quotes
+        let nets = parse_netlist(
+            "(export (nets (net (code 1) (name \"a \\\"b\\\"\") (node (ref R1) (pin 1)))))",
+        )
+        .unwrap();
+        assert_eq!(nets[0].name, "a \"b\"");
+        assert_eq!(nets[0].pins[0].to_string(), "R1.1");
+    }
+
+    #[test]
+    fn test_import() {
+        let nets = parse_netlist(NETLIST).unwrap();
+        let placement =
+            Placement::parse("# blinky\nD1.1 = 10\nD1.2 = 12\nR1.2 = 14\nR1.1 = 20\nQ1.1 = 30\n")
+                .unwrap();
+        // the board's special nets, with a bridge that the import replaces
+        let mut base = validate::initial_netlist();
+        base[0].color = Color([0x10, 0x20, 0x30]);
+        base[0].nodes.push(Node::Column(40));
+        let import = import(&nets, &placement, &base).unwrap();
+
+        assert_eq!(import.netlist.len(), 8);
+        assert_eq!(import.netlist[0].nodes, vec![Node::GND, Node::Column(10)]);
+        assert_eq!(import.netlist[0].color, Color([0x10, 0x20, 0x30]));
+        assert_eq!(import.netlist[7].name, "LED");
+        assert_eq!(
+            import.netlist[7].nodes,
+            vec![Node::Column(14), Node::Column(12)]
+        );
+        assert_eq!(
+            import.bridgelist,
+            vec![
+                (Node::GND, Node::Column(10)),
+                (Node::Column(14), Node::Column(12))
+            ]
+        );
+        assert_eq!(
+            import.problems,
+            vec![
+                Problem::UnmappedPin {
+                    net: "Net-(R1-Pad1)".to_string(),
+                    pin: Pin {
+                        reference: "U1".to_string(),
+                        pin: "16".to_string()
+                    }
+                },
+                Problem::UnknownPlacement {
+                    name: "Q1.1".to_string()
+                },
+            ]
+        );
+    }
+}
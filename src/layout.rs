@@ -0,0 +1,231 @@
+//! Arrangement of the nodes on the Jumperless, for drawing the board in a terminal.
+
+use crate::types::{Color, Net, Node};
+use std::collections::HashMap;
+
+/// Width of the row names, in front of the nodes
+pub const NAME_WIDTH: usize = 8;
+
+/// Colors to tell groups of nodes apart, when they have no colors of their own
+pub const PALETTE: [Color; 8] = [
+    Color([230, 25, 75]),
+    Color([60, 180, 75]),
+    Color([255, 225, 25]),
+    Color([0, 130, 200]),
+    Color([245, 130, 48]),
+    Color([145, 30, 180]),
+    Color([70, 240, 240]),
+    Color([240, 50, 230]),
+];
+
+/// A row of nodes, as drawn from left to right
+pub struct Row {
+    pub name: &'static str,
+    pub nodes: Vec<Node>,
+}
+
+impl Row {
+    /// Width of each node when drawn, including a space to separate it from the previous one.
+    /// Rows of named nodes share a width, so they line up.
+    pub fn cell_width(&self) -> usize {
+        if self
+            .nodes
+            .iter()
+            .all(|node| matches!(node, Node::Column(_)))
+        {
+            3
+        } else {
+            4
+        }
+    }
+}
+
+/// All nodes, in rows: the Nano header, the special nodes, the supply rails, and the two halves of the breadboard
+pub fn rows() -> Vec<Row> {
+    use Node::*;
+    vec![
+        Row {
+            name: "Nano",
+            nodes: vec![
+                NANO_D0, NANO_D1, NANO_D2, NANO_D3, NANO_D4, NANO_D5, NANO_D6, NANO_D7, NANO_D8,
+                NANO_D9, NANO_D10, NANO_D11, NANO_D12, NANO_D13, NANO_RESET, NANO_AREF,
+            ],
+        },
+        Row {
+            name: "",
+            nodes: vec![
+                NANO_A0, NANO_A1, NANO_A2, NANO_A3, NANO_A4, NANO_A5, NANO_A6, NANO_A7,
+            ],
+        },
+        Row {
+            name: "Special",
+            nodes: vec![
+                DAC0,
+                DAC1,
+                ISENSE_PLUS,
+                ISENSE_MINUS,
+                ADC0,
+                ADC1,
+                ADC2,
+                ADC3,
+                RP_GPIO_0,
+                RP_UART_Rx,
+                RP_UART_Tx,
+            ],
+        },
+        Row {
+            name: "Rails",
+            nodes: vec![GND, SUPPLY_5V, SUPPLY_3V3],
+        },
+        Row {
+            name: "Top",
+            nodes: (1..=30).map(Column).collect(),
+        },
+        Row {
+            name: "Bottom",
+            nodes: (31..=60).map(Column).collect(),
+        },
+    ]
+}
+
+/// Short label for the node, at most 3 characters wide
+pub fn label(node: Node) -> String {
+    use Node::*;
+    match node {
+        Column(n) => return n.to_string(),
+        GND => "GND",
+        SUPPLY_5V => "5V",
+        SUPPLY_3V3 => "3V3",
+        DAC0 => "DA0",
+        DAC1 => "DA1",
+        ISENSE_PLUS => "I+",
+        ISENSE_MINUS => "I-",
+        ADC0 => "AD0",
+        ADC1 => "AD1",
+        ADC2 => "AD2",
+        ADC3 => "AD3",
+        NANO_RESET => "RST",
+        NANO_AREF => "REF",
+        RP_GPIO_0 => "GP0",
+        RP_UART_Rx => "RX",
+        RP_UART_Tx => "TX",
+        // NANO_D0 -> D0, NANO_A0 -> A0
+        named => return named.to_string().trim_start_matches("NANO_").to_string(),
+    }
+    .to_string()
+}
+
+/// The net that contains the node, if any
+pub fn net_of(netlist: &[Net], node: Node) -> Option<&Net> {
+    netlist.iter().find(|net| net.nodes.contains(&node))
+}
+
+/// Draw the nodes of a row, each as returned by `cell` (given the node's index in the row, the node, and the
+/// width it has to fill). Nodes that do not fit within `width` characters are left out.
+pub fn draw_cells(
+    row: &Row,
+    width: usize,
+    mut cell: impl FnMut(usize, Node, usize) -> String,
+) -> String {
+    let cell_width = row.cell_width();
+    row.nodes
+        .iter()
+        .take(width / cell_width)
+        .enumerate()
+        .map(|(index, node)| cell(index, *node, cell_width))
+        .collect()
+}
+
+/// Escape sequence for drawing on the given background color, with black or white text, whichever is more readable
+pub fn paint(color: Color) -> String {
+    let Color([r, g, b]) = color;
+    let foreground = if color.is_light() { 30 } else { 97 };
+    format!("\x1b[48;2;{};{};{}m\x1b[{}m", r, g, b, foreground)
+}
+
+/// Draw the whole board: each row of nodes, and below it the mark of each node (e.g. its net number).
+/// Marks are drawn in their color with 24-bit ANSI escape sequences, unless `color` is false.
+pub fn draw_board(marks: &HashMap<Node, (String, Color)>, color: bool) -> Vec<String> {
+    let mut lines = vec![];
+    for row in rows() {
+        lines.push(format!(
+            "{:<2$}{}",
+            row.name,
+            draw_cells(&row, usize::MAX, |_, node, width| format!(
+                "{:>1$}",
+                label(node),
+                width
+            )),
+            NAME_WIDTH
+        ));
+        let marks = draw_cells(&row, usize::MAX, |_, node, width| match marks.get(&node) {
+            Some((mark, mark_color)) if color => {
+                format!("{}{:>2$}\x1b[0m", paint(*mark_color), mark, width)
+            }
+            Some((mark, _)) => format!("{:>1$}", mark, width),
+            None => format!("{:>1$}", "·", width),
+        });
+        lines.push(format!("{:1$}{2}", "", NAME_WIDTH, marks));
+    }
+    lines
+}
+
+/// Nodes connected by bridges, directly or through other nodes, in the order they first appear
+pub fn bridge_groups(bridges: &[(Node, Node)]) -> Vec<Vec<Node>> {
+    let mut groups: Vec<Vec<Node>> = vec![];
+    for (a, b) in bridges {
+        let found_a = groups.iter().position(|group| group.contains(a));
+        let found_b = groups.iter().position(|group| group.contains(b));
+        match (found_a, found_b) {
+            (Some(i), Some(j)) if i == j => {}
+            (Some(i), Some(j)) => {
+                let merged = groups.remove(j.max(i));
+                groups[j.min(i)].extend(merged);
+            }
+            (Some(i), None) => groups[i].push(*b),
+            (None, Some(j)) => groups[j].push(*a),
+            (None, None) => groups.push(vec![*a, *b]),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::NAMED_NODES;
+
+    #[test]
+    fn test_rows() {
+        let nodes: Vec<Node> = rows().into_iter().flat_map(|row| row.nodes).collect();
+        assert_eq!(nodes.len(), NAMED_NODES.len() + 60);
+        for node in NAMED_NODES {
+            assert!(nodes.contains(&node), "{} is missing", node);
+            assert!(label(node).len() <= 3, "label of {} is too long", node);
+        }
+    }
+
+    #[test]
+    fn test_bridge_groups() {
+        let bridges = [
+            (Node::Column(1), Node::Column(2)),
+            (Node::GND, Node::Column(10)),
+            (Node::Column(3), Node::Column(2)),
+            (Node::Column(10), Node::Column(1)),
+            (Node::Column(30), Node::Column(31)),
+        ];
+        assert_eq!(
+            bridge_groups(&bridges),
+            vec![
+                vec![
+                    Node::Column(1),
+                    Node::Column(2),
+                    Node::Column(3),
+                    Node::GND,
+                    Node::Column(10)
+                ],
+                vec![Node::Column(30), Node::Column(31)],
+            ]
+        );
+    }
+}
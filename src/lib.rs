@@ -1,9 +1,19 @@
+#[cfg(feature = "client")]
+pub mod client;
 pub mod device;
 pub mod device_manager;
+pub mod diff;
+pub mod graph;
+pub mod kicad;
+pub mod layout;
 pub mod logger;
 pub mod measurements;
+pub mod metrics;
 pub mod parser;
+pub mod render;
 #[cfg(feature = "server")]
 pub mod server;
+pub mod snapshot;
+pub mod state;
 pub mod types;
 pub mod validate;
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::io::Write;
 use std::sync::{Arc, Mutex};
 use std::{fs::File, path::Path};
@@ -67,3 +68,51 @@ impl DeviceLogger for FileLogger {
         self.write_line("SEND", line);
     }
 }
+
+/// A [`DeviceLogger`] that keeps the most recent events in memory, and passes all events on to another logger
+#[derive(Clone)]
+pub struct RecentLogger<L: DeviceLogger> {
+    inner: L,
+    recent: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl<L: DeviceLogger> RecentLogger<L> {
+    pub fn new(inner: L, capacity: usize) -> Self {
+        Self {
+            inner,
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// The most recent events, oldest first, formatted like `SEND ::getnetlist:1[]`
+    pub fn recent(&self) -> Vec<String> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn push(&self, tag: &str, arg: &str) {
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(format!("{} {}", tag, arg));
+    }
+}
+
+impl<L: DeviceLogger> DeviceLogger for RecentLogger<L> {
+    fn open(&self, path: &str) {
+        self.push("OPEN", path);
+        self.inner.open(path);
+    }
+
+    fn received(&self, line: &str) {
+        self.push("RECV", line);
+        self.inner.received(line);
+    }
+
+    fn sent(&self, line: &str) {
+        self.push("SEND", line);
+        self.inner.sent(line);
+    }
+}
@@ -1,21 +1,44 @@
+use anyhow::Context;
+use board::Board;
 use clap::{Parser, Subcommand, ValueEnum};
 use comfy_table::{modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Table};
 use device_manager::PortRole;
 use env_logger::Env;
 use log::info;
 use shadow_rs::shadow;
+use std::collections::HashMap;
 use std::fs::File;
-use types::SupplySwitchPos;
+use std::io::IsTerminal;
+use types::{Bridgelist, SupplySwitchPos};
 
 shadow!(build);
 
+mod apply;
+mod board;
+// only parts of the client API are used by the CLI
+#[cfg(feature = "client")]
+#[allow(dead_code)]
+mod client;
 mod device;
 mod device_manager;
+mod diff;
+mod graph;
+mod kicad;
+mod layout;
 pub mod logger;
 mod measurements;
+mod metrics;
 mod parser;
+mod render;
 #[cfg(feature = "server")]
 mod server;
+mod shell;
+mod snapshot;
+mod state;
+#[cfg(unix)]
+mod terminal;
+#[cfg(unix)]
+mod tui;
 mod types;
 mod validate;
 
@@ -26,15 +49,44 @@ struct Cli {
     #[arg(long, short)]
     port: Option<String>,
 
+    /// URL of a running `jlctl server`, to send commands through, instead of using the serial port directly
+    ///
+    /// `https://` URLs (with the `tls` feature) need a certificate issued by a well-known certificate authority,
+    /// or set `JLCTL_CA_CERT` to a PEM file with the certificate to trust (e.g. the one stored by `server --tls-self-signed`).
+    #[cfg(feature = "client")]
+    #[arg(long, short, conflicts_with = "port")]
+    remote: Option<String>,
+
+    /// Always use the serial port directly, even if a local `jlctl server` is running
+    #[cfg(feature = "client")]
+    #[arg(long, conflicts_with = "remote")]
+    local: bool,
+
     /// Capture device log in this file
     #[arg(long, short, default_value = "log.txt")]
     log_path: String,
 
+    /// Print the lines that would be sent to the device, instead of sending them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// With --dry-run, answer reads from this state: `board` (read once from the board),
+    /// `snapshot:<name>`, or a file containing a netlist (JSON), a list of bridges, or a snapshot.
+    /// Parts that are not given start out empty.
+    #[arg(long, requires = "dry_run")]
+    dry_run_from: Option<String>,
+
+    /// Directory where snapshots are stored, for `--dry-run-from snapshot:<name>` when using the serial port directly
+    #[arg(long, requires = "dry_run_from", default_value = snapshot::DEFAULT_DIR)]
+    dry_run_snapshot_dir: std::path::PathBuf,
+
     #[command(subcommand)]
     command: Command,
 }
 
 #[derive(Debug, Subcommand)]
+// parsed once, so the size of the `Server` variant does not matter
+#[allow(clippy::large_enum_variant)]
 enum Command {
     /// List serial ports
     #[command()]
@@ -73,11 +125,13 @@ enum Command {
     Bridge(BridgeCommand),
 
     /// Inform Jumperless about it's switch position
+    ///
+    /// Prints the currently configured position, if no position is given.
     #[command()]
     SupplySwitchPos {
         /// One of: 8V, 3.3V, 5V
         #[arg()]
-        pos: SupplySwitchPos,
+        pos: Option<SupplySwitchPos>,
     },
 
     /// Set color for given light
@@ -92,20 +146,284 @@ enum Command {
         color: String,
     },
 
+    /// Bring the board into the state described by a file, sending only what differs
+    ///
+    /// The file contains a netlist (JSON, as written by `net list -o json`), a list of bridges
+    /// (as accepted by `bridge set`), or a JSON object with any of `netlist`, `bridgelist` and `supply_switch` (e.g. a snapshot).
+    /// After applying, the state is read back, and any differences are reported.
+    #[command()]
+    Apply {
+        /// File to read. Use `-` for stdin.
+        #[arg()]
+        file: String,
+
+        /// Only print the planned changes, without sending anything.
+        ///
+        /// Unlike the global `--dry-run`, which prints the lines that would be sent to the device, this prints what would change.
+        #[arg(long)]
+        plan: bool,
+    },
+
+    /// Compare two board states, e.g. the live board with a circuit file
+    ///
+    /// FROM and TO are each one of: `board` (the live board), `snapshot:<name>` (a saved snapshot),
+    /// or a file containing a netlist (JSON), a list of bridges, or a snapshot.
+    /// Only the parts present in both are compared.
+    #[command()]
+    Diff {
+        #[arg()]
+        from: String,
+
+        #[arg(default_value = "board")]
+        to: String,
+
+        /// Output format
+        #[arg(long, short, value_enum, default_value = "text")]
+        output_format: DiffOutputFormat,
+
+        /// Exit with status 1 if there are differences
+        #[arg(long)]
+        exit_code: bool,
+
+        /// Directory where snapshots are stored, when using the serial port directly
+        #[arg(long, default_value = snapshot::DEFAULT_DIR)]
+        snapshot_dir: std::path::PathBuf,
+    },
+
+    /// Draw the board as SVG or PNG image, with nodes in the color of their nets and bridges as arcs
+    ///
+    /// SOURCE is one of: `board` (the live board), `snapshot:<name>` (a saved snapshot),
+    /// or a file containing a netlist (JSON), a list of bridges, or a snapshot.
+    #[command()]
+    Render {
+        #[arg(default_value = "board")]
+        source: String,
+
+        /// Image format
+        #[arg(long, value_enum, default_value = "svg")]
+        format: RenderFormat,
+
+        /// Write to file instead of stdout
+        #[arg(long, short)]
+        file: Option<String>,
+
+        /// Title, drawn above the board
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Note to draw next to a node, given as <node>=<text> (e.g. `17=LED anode`). Can be given multiple times.
+        #[arg(long, value_parser = parse_annotation)]
+        annotate: Vec<(types::Node, String)>,
+
+        /// Pixels per unit, for PNG images (1 to 8)
+        #[arg(long, default_value = "2", value_parser = clap::value_parser!(u32).range(1..=render::MAX_SCALE as i64))]
+        scale: u32,
+
+        /// Directory where snapshots are stored, when using the serial port directly
+        #[arg(long, default_value = snapshot::DEFAULT_DIR)]
+        snapshot_dir: std::path::PathBuf,
+    },
+
+    /// Show status of the crosspoint switch chips
+    #[command()]
+    ChipStatus {
+        /// Write to file instead of stdout
+        #[arg(long, short)]
+        file: Option<String>,
+
+        /// Output format
+        #[arg(long, short, value_enum, default_value = "table")]
+        output_format: OutputFormat,
+    },
+
+    /// Interactive shell, which keeps the connection to the board open between commands
+    ///
+    /// Accepts the commands that operate on the board (e.g. `bridge add 3-7`), with tab completion of
+    /// commands and node names. Entered lines are kept in `.jlctl/history`. Leave with `exit` or Ctrl-D.
+    #[command()]
+    Shell,
+
+    /// Full-screen terminal UI, showing the board with net colors, the nets, chip status and the device log
+    ///
+    /// Bridges can be added and removed by selecting their ends with the keyboard.
+    #[cfg(unix)]
+    #[command()]
+    Tui {
+        /// How often to read the board's state, in milliseconds
+        #[arg(long, default_value = "1000")]
+        interval: u64,
+    },
+
+    /// Undo / redo changes made through a running `jlctl server`
+    #[cfg(feature = "client")]
+    #[command(subcommand)]
+    History(HistoryCommand),
+
+    /// Acquire / release exclusive write access to a running `jlctl server`
+    #[cfg(feature = "client")]
+    #[command(subcommand)]
+    Lease(LeaseCommand),
+
+    /// Save, restore and compare snapshots of the complete board state
+    ///
+    /// Uses the snapshots of a running `jlctl server`, if there is one.
+    #[command()]
+    Snapshot {
+        /// Directory where snapshots are stored, when using the serial port directly
+        #[arg(long, default_value = snapshot::DEFAULT_DIR)]
+        dir: std::path::PathBuf,
+
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+
     #[cfg(feature = "server")]
     /// Start HTTP server
     #[command()]
     Server {
-        /// Address to listen on. Use `localhost:0` to pick a random port.
+        /// Address to listen on. Use `localhost:0` to pick a random port, or `unix:<path>` to listen on a Unix domain socket.
         #[arg(long, short, default_value = "localhost:8080")]
         listen: String,
+
+        /// Permissions (octal) of the socket file, when listening on a Unix domain socket
+        #[cfg(unix)]
+        #[arg(long, default_value = "600", value_parser = parse_mode)]
+        socket_mode: u32,
+
+        /// Interval (in milliseconds) in which the device is polled for changes, while clients are subscribed to `/events`. Use 0 to disable polling.
+        #[arg(long, default_value = "2000")]
+        poll_interval: u64,
+
+        /// Allow sending raw instructions to the Jumperless via `POST /raw`
+        #[arg(long)]
+        allow_raw: bool,
+
+        /// Maximum time (in milliseconds) a request may wait for the device, before failing with status 504
+        #[arg(long, default_value = "10000")]
+        request_timeout: u64,
+
+        /// Require clients to send this bearer token. Grants full access. Can be given multiple times.
+        #[arg(long)]
+        token: Vec<String>,
+
+        /// Bearer token that only grants read access. Can be given multiple times.
+        #[arg(long)]
+        read_only_token: Vec<String>,
+
+        /// Require clients to use basic auth with this user, given as <user>:<password>. Grants full access. Can be given multiple times.
+        #[arg(long)]
+        user: Vec<String>,
+
+        /// User for basic auth, given as <user>:<password>, that only grants read access. Can be given multiple times.
+        #[arg(long)]
+        read_only_user: Vec<String>,
+
+        /// Origin that is allowed to make cross-origin requests (e.g. `http://192.168.1.5:3000`, or `*` for any).
+        /// Can be given multiple times. If omitted, `http://localhost:<port>` origins are allowed.
+        /// Write requests from pages on other origins are rejected.
+        #[arg(long)]
+        allow_origin: Vec<String>,
+
+        /// Serve jumperlab from this directory (e.g. jumperlab's build output), instead of the embedded copy.
+        /// Open pages are reloaded when files in the directory change.
+        #[arg(long)]
+        ui_dir: Option<std::path::PathBuf>,
+
+        /// Number of board states to keep, for undo / redo
+        #[arg(long, default_value = "50")]
+        history_size: usize,
+
+        /// Longest duration (in seconds) clients can acquire a lease for
+        #[arg(long, default_value = "3600")]
+        max_lease: u64,
+
+        /// Directory where named snapshots are stored
+        #[arg(long, default_value = snapshot::DEFAULT_DIR)]
+        snapshot_dir: std::path::PathBuf,
+
+        /// Serve via HTTPS, using the certificate (chain) from this PEM file
+        #[cfg(feature = "tls")]
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<std::path::PathBuf>,
+
+        /// Private key for `--tls-cert`, in PEM format
+        #[cfg(feature = "tls")]
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<std::path::PathBuf>,
+
+        /// Serve via HTTPS, using a self-signed certificate.
+        /// If `--tls-cert` and `--tls-key` are given as well, the certificate is stored there (if they don't exist yet), and reused on the next start.
+        ///
+        /// This is meant for browsers, which can be told to trust the certificate. For the CLI's `--remote`, set `JLCTL_CA_CERT` to the stored certificate.
+        #[cfg(feature = "tls")]
+        #[arg(long)]
+        tls_self_signed: bool,
+
+        /// Additional host name (or IP address) for the self-signed certificate. Can be given multiple times.
+        #[cfg(feature = "tls")]
+        #[arg(long, requires = "tls_self_signed")]
+        tls_hostname: Vec<String>,
     },
 
+    /// Convert circuits designed with other tools into a netlist or list of bridges
+    #[command(subcommand)]
+    Import(ImportCommand),
+
     #[command()]
     /// Experimental measurement interface
     DumpMeasurements,
 }
 
+#[derive(Debug, Subcommand)]
+enum ImportCommand {
+    /// Convert a KiCad netlist (`.net` file, s-expression format), placing its pins as given by a placement file
+    ///
+    /// The placement file has one `<reference>.<pin> = <node>` per line (e.g. `R1.2 = 17` or `D1.1 = NANO_D13`),
+    /// and may place whole nets with `net:<name> = <node>`. Nets named GND, +5V, +3V3 or +3.3V are connected
+    /// to the matching rail. Pins that are not placed, and nets that cannot be represented, are reported.
+    #[command()]
+    Kicad {
+        /// KiCad netlist to convert
+        #[arg()]
+        netlist: String,
+
+        /// File that maps component pins to nodes
+        #[arg(long, short)]
+        placement: String,
+
+        /// Write to file instead of stdout
+        #[arg(long, short)]
+        file: Option<String>,
+
+        /// Output format: a netlist (JSON, as accepted by `net send` and `apply`), or bridges (as accepted by `bridge set`)
+        #[arg(long, short, value_enum, default_value = "netlist")]
+        output_format: ImportOutputFormat,
+
+        /// Fail if anything could not be converted, instead of only reporting it
+        #[arg(long)]
+        strict: bool,
+
+        /// Where to take the special nets (GND, supplies, ...) from, so their colors stay as the device has them:
+        /// `board`, `snapshot:<name>`, or a file containing a netlist (JSON) or a snapshot
+        ///
+        /// If not given, they are taken from the board, or if that cannot be read, have the colors the device uses after a reset.
+        #[arg(long)]
+        base: Option<String>,
+
+        /// Directory where snapshots are stored, for `--base snapshot:<name>` when using the serial port directly
+        #[arg(long, default_value = snapshot::DEFAULT_DIR)]
+        snapshot_dir: std::path::PathBuf,
+    },
+}
+
+#[derive(ValueEnum, Copy, Clone, PartialEq, Debug)]
+enum ImportOutputFormat {
+    #[value()]
+    Netlist,
+    #[value()]
+    Bridges,
+}
+
 #[derive(Debug, Subcommand)]
 enum NetCommand {
     /// Download list of nets from the Jumperless
@@ -115,9 +433,10 @@ enum NetCommand {
         #[arg(long, short)]
         file: Option<String>,
 
-        /// Output format
+        /// Output format. `board` draws the board, with the number of its net below each node.
+        /// `dot` (Graphviz) and `graphml` export the nets and bridges as graph.
         #[arg(long, short, value_enum, default_value = "table")]
-        output_format: OutputFormat,
+        output_format: NetOutputFormat,
     },
 
     /// Upload list of nets (in JSON format) to the Jumperless
@@ -137,6 +456,20 @@ enum OutputFormat {
     Json,
 }
 
+#[derive(ValueEnum, Copy, Clone, PartialEq, Debug)]
+enum NetOutputFormat {
+    #[value()]
+    Table,
+    #[value()]
+    Json,
+    #[value()]
+    Board,
+    #[value()]
+    Dot,
+    #[value()]
+    Graphml,
+}
+
 #[derive(Debug, Subcommand)]
 enum BridgeCommand {
     /// Download list of bridges from the Jumperless
@@ -146,7 +479,7 @@ enum BridgeCommand {
         #[arg(long, short)]
         file: Option<String>,
 
-        /// Output format
+        /// Output format. `board` draws the board, numbering the groups of bridged nodes below each node.
         #[arg(long, short, value_enum, default_value = "list")]
         output_format: BridgeOutputFormat,
     },
@@ -179,17 +512,150 @@ enum BridgeCommand {
         file: Option<String>,
     },
 
+    /// Remove one or more bridges from the current netlist
+    ///
+    /// Either `--file` or `[bridges]` must be specified (but not both).
+    #[command()]
+    Remove {
+        /// Bridge(s) to remove, e.g. "GND-17" or "12-17,14-29"
+        #[arg()]
+        bridges: Option<String>,
+
+        /// Read bridges from file
+        #[arg(long, short)]
+        file: Option<String>,
+    },
+
     /// Upload empty list of bridges to the jumperless
     #[command()]
     Clear,
 }
 
+#[cfg(feature = "client")]
+#[derive(Debug, Subcommand)]
+enum HistoryCommand {
+    /// List recorded board states
+    #[command()]
+    List {
+        /// Output format
+        #[arg(long, short, value_enum, default_value = "table")]
+        output_format: OutputFormat,
+    },
+
+    /// Return to the state before the last change
+    #[command()]
+    Undo,
+
+    /// Reapply the last undone change
+    #[command()]
+    Redo,
+
+    /// Apply a recorded state (recorded as a new change, which can be undone)
+    #[command()]
+    Restore {
+        /// ID of the entry, as shown by `history list`
+        #[arg()]
+        id: u64,
+    },
+}
+
+#[cfg(feature = "client")]
+#[derive(Debug, Subcommand)]
+enum LeaseCommand {
+    /// Show who holds the lease, if anyone
+    #[command()]
+    Status,
+
+    /// Acquire the lease, or renew it if `JLCTL_LEASE` is set. Prints the token.
+    #[command()]
+    Acquire {
+        /// Name shown to others. Defaults to the current user.
+        #[arg(long)]
+        holder: Option<String>,
+
+        /// Duration (in seconds)
+        #[arg(long, default_value = "600")]
+        duration: u64,
+    },
+
+    /// Release the lease given in `JLCTL_LEASE`
+    #[command()]
+    Release,
+}
+
+#[derive(Debug, Subcommand)]
+enum SnapshotCommand {
+    /// Save the board's current state (nets, bridges, supply switch, lights) under the given name
+    ///
+    /// Lights cannot be read back from the device. They are only recorded when going through a
+    /// running `jlctl server`, which remembers the lights it has set.
+    #[command()]
+    Save {
+        #[arg()]
+        name: String,
+    },
+
+    /// Apply a saved snapshot to the board, and verify it by reading the state back
+    ///
+    /// Lights in the snapshot are set, but not verified. Other lights are left as they are.
+    #[command()]
+    Restore {
+        #[arg()]
+        name: String,
+    },
+
+    /// List saved snapshots
+    #[command()]
+    List {
+        /// Output format
+        #[arg(long, short, value_enum, default_value = "table")]
+        output_format: OutputFormat,
+    },
+
+    /// Show what changed from one snapshot to another, or to the board's current state
+    ///
+    /// When comparing with the board without a server, lights are not compared, since they
+    /// cannot be read back from the device.
+    #[command()]
+    Diff {
+        #[arg()]
+        from: String,
+
+        /// Snapshot to compare with. If omitted, compares with the board's current state.
+        #[arg()]
+        to: Option<String>,
+
+        /// Output format
+        #[arg(long, short, value_enum, default_value = "text")]
+        output_format: DiffOutputFormat,
+    },
+}
+
+#[derive(ValueEnum, Copy, Clone, PartialEq, Debug)]
+enum DiffOutputFormat {
+    /// One change per line, colored when writing to a terminal
+    #[value()]
+    Text,
+    #[value()]
+    Json,
+}
+
+#[derive(ValueEnum, Copy, Clone, PartialEq, Debug)]
+enum RenderFormat {
+    #[value()]
+    Svg,
+    #[value()]
+    Png,
+}
+
 #[derive(ValueEnum, Copy, Clone, PartialEq, Debug)]
 enum BridgeOutputFormat {
     #[value()]
     List,
     #[value()]
     Json,
+    #[value()]
+    Board,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -198,7 +664,7 @@ fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
 
     let mut device_manager = device_manager::DeviceManager::new(
-        args.port,
+        args.port.clone(),
         logger::FileLogger::new("log.txt").expect("open device log"),
     );
 
@@ -249,173 +715,948 @@ fn main() -> anyhow::Result<()> {
     }
 
     #[cfg(feature = "server")]
-    if let Command::Server { listen } = args.command {
-        server::start(device_manager, Some(&listen)).expect("Start server");
+    if let Command::Server {
+        listen,
+        #[cfg(unix)]
+        socket_mode,
+        poll_interval,
+        allow_raw,
+        request_timeout,
+        token,
+        read_only_token,
+        user,
+        read_only_user,
+        allow_origin,
+        ui_dir,
+        history_size,
+        max_lease,
+        snapshot_dir,
+        #[cfg(feature = "tls")]
+        tls_cert,
+        #[cfg(feature = "tls")]
+        tls_key,
+        #[cfg(feature = "tls")]
+        tls_self_signed,
+        #[cfg(feature = "tls")]
+        tls_hostname,
+    } = args.command
+    {
+        let mut auth = server::Auth::default();
+        for token in token {
+            auth.add_token(token, server::Role::ReadWrite);
+        }
+        for token in read_only_token {
+            auth.add_token(token, server::Role::ReadOnly);
+        }
+        for user in user {
+            auth.add_user(&user, server::Role::ReadWrite)?;
+        }
+        for user in read_only_user {
+            auth.add_user(&user, server::Role::ReadOnly)?;
+        }
+        let config = server::Config {
+            poll_interval: (poll_interval > 0)
+                .then(|| std::time::Duration::from_millis(poll_interval)),
+            allow_raw,
+            request_timeout: std::time::Duration::from_millis(request_timeout),
+            auth,
+            allowed_origins: allow_origin,
+            #[cfg(feature = "tls")]
+            tls: tls_config(&listen, tls_cert, tls_key, tls_self_signed, tls_hostname),
+            listen_address: Some(listen),
+            #[cfg(unix)]
+            socket_mode,
+            ui_dir,
+            history_size,
+            max_lease: std::time::Duration::from_secs(max_lease),
+            snapshot_dir,
+        };
+        server::start(device_manager, config).expect("Start server");
+        return Ok(());
+    }
+
+    if let Command::Import(ImportCommand::Kicad {
+        netlist,
+        placement,
+        file,
+        output_format,
+        strict,
+        base,
+        snapshot_dir,
+    }) = args.command
+    {
+        let read = |path: &str| {
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))
+        };
+        let nets = kicad::parse_netlist(&read(&netlist)?)
+            .with_context(|| format!("Failed to parse {:?}", netlist))?;
+        let placement = kicad::Placement::parse(&read(&placement)?)
+            .with_context(|| format!("Failed to parse {:?}", placement))?;
+        let base = with_snapshots(
+            #[cfg(feature = "client")]
+            remote_client(args.remote, args.local, args.port.is_some())?,
+            &mut device_manager,
+            snapshot_dir,
+            |snapshots| {
+                match &base {
+                Some(base) => load_source(snapshots, base)?
+                    .netlist
+                    .ok_or_else(|| anyhow::anyhow!("{:?} contains no netlist", base)),
+                None => Ok(snapshots
+                    .current()
+                    .map(|current| current.state.netlist)
+                    .unwrap_or_else(|err| {
+                        eprintln!(
+                            "Warning: Could not read the special nets from the board, using the default colors: {:#}",
+                            err
+                        );
+                        validate::initial_netlist()
+                    })),
+            }
+            },
+        )?;
+        let import = kicad::import(&nets, &placement, &base)?;
+        for problem in &import.problems {
+            eprintln!("Warning: {}", problem);
+        }
+        if strict && !import.problems.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} problems while importing {:?}",
+                import.problems.len(),
+                netlist
+            ));
+        }
+        let mut output = file_or_stdout(file)?;
+        match output_format {
+            ImportOutputFormat::Netlist => {
+                serde_json::to_writer_pretty(&mut output, &import.netlist)?;
+            }
+            ImportOutputFormat::Bridges => {
+                let bridges: Vec<String> = import
+                    .bridgelist
+                    .iter()
+                    .map(|(a, b)| format!("{}-{}", a, b))
+                    .collect();
+                output.write_all(bridges.join(",").as_bytes())?;
+            }
+        }
+        output.write_all(b"\n")?;
         return Ok(());
     }
 
     if let Command::DumpMeasurements = args.command {
         measurements::dump_measurements().expect("Failed to dump measurements");
+        return Ok(());
+    }
+
+    if args.dry_run {
+        if !is_board_command(&args.command) && !matches!(args.command, Command::Shell) {
+            return Err(anyhow::anyhow!(
+                "--dry-run only works with commands that operate on the board"
+            ));
+        }
+        let mut state = state::BoardState {
+            netlist: vec![],
+            bridgelist: vec![],
+            supply_switch: SupplySwitchPos::V5,
+        };
+        if let Some(source) = args.dry_run_from {
+            let from = with_snapshots(
+                #[cfg(feature = "client")]
+                remote_client(args.remote, args.local, args.port.is_some())?,
+                &mut device_manager,
+                args.dry_run_snapshot_dir,
+                |snapshots| load_source(snapshots, &source),
+            )?;
+            state.netlist = from.netlist.unwrap_or(state.netlist);
+            state.bridgelist = from.bridgelist.unwrap_or(state.bridgelist);
+            state.supply_switch = from.supply_switch.unwrap_or(state.supply_switch);
+        }
+        let mut board = board::DryRunBoard::new(state);
+        if let Command::Shell = args.command {
+            return shell::run(shell::Target::Board(&mut board, "dry run".to_string()));
+        }
+        return run_command(&mut board, args.command);
+    }
+
+    #[cfg(unix)]
+    if let Command::Tui { interval } = args.command {
+        let interval = std::time::Duration::from_millis(interval);
+        #[cfg(feature = "client")]
+        if let Some(mut client) = remote_client(args.remote, args.local, args.port.is_some())? {
+            let name = client.base_url().to_string();
+            return tui::run(tui::Target::Board(&mut client, name), interval);
+        }
+        let logger =
+            logger::RecentLogger::new(logger::FileLogger::new(&args.log_path)?, tui::LOG_SIZE);
+        let device_manager = device_manager::DeviceManager::new(args.port, logger.clone());
+        return tui::run(tui::Target::Device(device_manager, logger), interval);
+    }
+
+    if let Command::Shell = args.command {
+        #[cfg(feature = "client")]
+        if let Some(mut client) = remote_client(args.remote, args.local, args.port.is_some())? {
+            let name = client.base_url().to_string();
+            return shell::run(shell::Target::Board(&mut client, name));
+        }
+        let result = shell::run(shell::Target::Device(&mut device_manager));
+        device_manager.close_device();
+        return result;
+    }
+
+    #[cfg(feature = "client")]
+    if let Command::History(history_command) = args.command {
+        let client =
+            remote_client(args.remote, args.local, args.port.is_some())?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The history is kept by `jlctl server`. Start it, or pass --remote <URL>"
+                )
+            })?;
+        return run_history_command(&client, history_command);
     }
 
-    device_manager.with_device(|device| {
-        match args.command {
-            Command::SupplySwitchPos { pos } => {
-                device.set_supply_switch(pos)?;
+    #[cfg(feature = "client")]
+    if let Command::Lease(lease_command) = args.command {
+        let client =
+            remote_client(args.remote, args.local, args.port.is_some())?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Leases are held on `jlctl server`. Start it, or pass --remote <URL>"
+                )
+            })?;
+        return run_lease_command(&client, lease_command);
+    }
+
+    if let Command::Diff {
+        from,
+        to,
+        output_format,
+        exit_code,
+        snapshot_dir,
+    } = args.command
+    {
+        let changes = with_snapshots(
+            #[cfg(feature = "client")]
+            remote_client(args.remote, args.local, args.port.is_some())?,
+            &mut device_manager,
+            snapshot_dir,
+            |snapshots| diff_sources(snapshots, &from, &to),
+        )?;
+        print_changes(&changes, output_format)?;
+        if exit_code && !changes.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Command::Render {
+        source,
+        format,
+        file,
+        title,
+        annotate,
+        scale,
+        snapshot_dir,
+    } = args.command
+    {
+        let state = with_snapshots(
+            #[cfg(feature = "client")]
+            remote_client(args.remote, args.local, args.port.is_some())?,
+            &mut device_manager,
+            snapshot_dir,
+            |snapshots| load_source(snapshots, &source),
+        )?;
+        let options = render::Options {
+            title,
+            annotations: annotate,
+        };
+        let drawing = render::draw(
+            state.netlist.as_deref(),
+            &state.bridgelist.unwrap_or_default(),
+            &options,
+        );
+        let image = match format {
+            RenderFormat::Svg => drawing.to_svg().into_bytes(),
+            RenderFormat::Png => drawing.to_png(scale)?,
+        };
+        file_or_stdout(file)?.write_all(&image)?;
+        return Ok(());
+    }
+
+    if let Command::Snapshot { dir, command } = args.command {
+        return with_snapshots(
+            #[cfg(feature = "client")]
+            remote_client(args.remote, args.local, args.port.is_some())?,
+            &mut device_manager,
+            dir,
+            |snapshots| run_snapshot_command(snapshots, command),
+        );
+    }
+
+    #[cfg(feature = "client")]
+    if let Some(mut client) = remote_client(args.remote, args.local, args.port.is_some())? {
+        return run_command(&mut client, args.command);
+    }
+
+    device_manager.with_device(|device| run_command(device, args.command))?;
+
+    device_manager.close_device();
+
+    Ok(())
+}
+
+/// Determine which server to send commands to, if any.
+///
+/// Unless `--local` or `--port` is given, this checks if a server is running on the default address.
+/// That way the CLI keeps working while `jlctl server` holds the serial port.
+///
+/// If the `JLCTL_TOKEN` environment variable is set, it is sent as bearer token.
+/// If `JLCTL_LEASE` is set, it is sent as lease token.
+/// If `JLCTL_CA_CERT` is set (with the `tls` feature), only the certificates in that PEM file are trusted.
+#[cfg(feature = "client")]
+fn remote_client(
+    remote: Option<String>,
+    local: bool,
+    port: bool,
+) -> anyhow::Result<Option<client::Client>> {
+    let configure = |client: client::Client| -> anyhow::Result<client::Client> {
+        let client = match std::env::var("JLCTL_TOKEN") {
+            Ok(token) => client.with_token(&token),
+            Err(_) => client,
+        };
+        let client = match std::env::var("JLCTL_LEASE") {
+            Ok(token) => client.with_lease(&token),
+            Err(_) => client,
+        };
+        #[cfg(feature = "tls")]
+        if let Ok(path) = std::env::var("JLCTL_CA_CERT") {
+            let pem = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {:?}", path))?;
+            return client
+                .with_ca_cert(&pem)
+                .with_context(|| format!("Failed to load certificates from {:?}", path));
+        }
+        Ok(client)
+    };
+    if let Some(url) = remote {
+        return Ok(Some(configure(client::Client::new(&url))?));
+    }
+    if local || port {
+        return Ok(None);
+    }
+    let client = configure(client::Client::new(client::DEFAULT_URL))?;
+    if !client.is_reachable() {
+        return Ok(None);
+    }
+    info!("Using jlctl server at {}", client.base_url());
+    Ok(Some(client))
+}
+
+/// Parse a note for `render --annotate`, given as <node>=<text>
+fn parse_annotation(annotation: &str) -> anyhow::Result<(types::Node, String)> {
+    let (node, text) = annotation
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Expected <node>=<text>"))?;
+    Ok((types::Node::parse(node.trim())?, text.to_string()))
+}
+
+/// Parse file permissions given in octal (e.g. `660`)
+#[cfg(all(unix, feature = "server"))]
+fn parse_mode(mode: &str) -> Result<u32, std::num::ParseIntError> {
+    u32::from_str_radix(mode, 8)
+}
+
+/// Determine TLS configuration from the `server` options
+#[cfg(feature = "tls")]
+fn tls_config(
+    listen: &str,
+    cert: Option<std::path::PathBuf>,
+    key: Option<std::path::PathBuf>,
+    self_signed: bool,
+    extra_hostnames: Vec<String>,
+) -> Option<server::Tls> {
+    if !self_signed {
+        return cert
+            .zip(key)
+            .map(|(cert, key)| server::Tls::Files { cert, key });
+    }
+    let mut hostnames = server::Tls::default_hostnames(listen);
+    hostnames.extend(extra_hostnames);
+    Some(server::Tls::SelfSigned {
+        hostnames,
+        store: cert.zip(key),
+    })
+}
+
+/// Whether the command is handled by [`run_command`], so it can be used with `--dry-run` and in the shell
+fn is_board_command(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::SupplySwitchPos { .. }
+            | Command::Lightnet { .. }
+            | Command::Apply { .. }
+            | Command::ChipStatus { .. }
+            | Command::Raw { .. }
+            | Command::Net(_)
+            | Command::Bridge(_)
+    )
+}
+
+/// Run one of the commands that operate on the board
+fn run_command(board: &mut dyn Board, command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::SupplySwitchPos { pos: Some(pos) } => {
+            board.set_supply_switch(pos)?;
+        }
+
+        Command::SupplySwitchPos { pos: None } => {
+            println!("{}", board.supply_switch()?);
+        }
+
+        Command::Lightnet { name, color } => {
+            board.lightnet(name, color.try_into()?)?;
+        }
+
+        Command::Apply { file, plan } => {
+            let mut input = String::new();
+            file_or_stdin((file != "-").then_some(file))?.read_to_string(&mut input)?;
+            let target = diff::PartialState::parse(&input)?;
+
+            if plan {
+                let plan = apply::plan(board, &target)?;
+                if plan.steps.is_empty() {
+                    println!("Nothing to do, the board is up to date");
+                }
+                for change in &plan.changes {
+                    println!("{}", change);
+                }
+                for step in &plan.steps {
+                    println!("Would {}", step);
+                }
+                return Ok(());
             }
 
-            Command::Lightnet { name, color } => {
-                device.lightnet(name, color.try_into()?)?;
+            let report = apply::apply(board, &target)?;
+            if report.plan.steps.is_empty() {
+                println!("Nothing to do, the board is up to date");
+            }
+            for change in &report.plan.changes {
+                println!("{}", change);
             }
+            if !report.mismatches.is_empty() {
+                eprintln!("After applying, the board differs from the file (- missing on the board, + only on the board):");
+                for change in &report.mismatches {
+                    eprintln!("  {}", change);
+                }
+                return Err(anyhow::anyhow!("The board did not reach the desired state"));
+            }
+        }
 
-            Command::Raw { instruction, args } => {
-                let (success, messages) = device.raw(instruction, args.unwrap_or_default())?;
-                println!("Success: {success:?}");
-                println!("Captured messages: {messages:#?}");
+        Command::ChipStatus {
+            file,
+            output_format,
+        } => {
+            let chipstatus = board.chipstatus()?;
+            let mut output = file_or_stdout(file)?;
+            match output_format {
+                OutputFormat::Table => {
+                    let mut table = Table::new();
+                    table
+                        .load_preset(UTF8_FULL)
+                        .apply_modifier(UTF8_ROUND_CORNERS)
+                        .set_header(vec!["Chip", "X", "Y"]);
+                    for chip in chipstatus {
+                        table.add_row(vec![
+                            chip.char.to_string(),
+                            format_status(&chip.x_status),
+                            format_status(&chip.y_status),
+                        ]);
+                    }
+                    writeln!(&mut output, "{}", table)?;
+                }
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(&mut output, &chipstatus)?;
+                    output.write_all(b"\n")?;
+                }
             }
+        }
 
-            Command::Net(net_command) => match net_command {
-                NetCommand::List {
-                    file,
-                    output_format,
-                } => {
-                    let mut output = file_or_stdout(file)?;
-                    let netlist = device.netlist()?;
-                    match output_format {
-                        OutputFormat::Table => {
-                            let mut table = Table::new();
-                            table
-                                .load_preset(UTF8_FULL)
-                                .apply_modifier(UTF8_ROUND_CORNERS)
-                                .set_header(vec![
-                                    "Index", "Number", "Nodes", "Special", "Color", "Machine",
-                                    "Name",
-                                ]);
-                            for net in netlist {
-                                table.add_row(vec![
-                                    net.index.to_string(),
-                                    net.number.to_string(),
-                                    net.nodes
-                                        .iter()
-                                        .map(|n| n.to_string())
-                                        .collect::<Vec<String>>()
-                                        .join(", "),
-                                    net.special.to_string(),
-                                    net.color.to_string(),
-                                    net.machine.to_string(),
-                                    net.name,
-                                ]);
-                            }
-                            writeln!(&mut output, "{}", table)?;
+        Command::Raw { instruction, args } => {
+            let (success, messages) = board.raw(instruction, args.unwrap_or_default())?;
+            println!("Success: {success:?}");
+            println!("Captured messages: {messages:#?}");
+        }
+
+        Command::Net(net_command) => match net_command {
+            NetCommand::List {
+                file,
+                output_format,
+            } => {
+                let color = file.is_none() && use_color();
+                let mut output = file_or_stdout(file)?;
+                let netlist = board.netlist()?;
+                match output_format {
+                    NetOutputFormat::Table => {
+                        let mut table = Table::new();
+                        table
+                            .load_preset(UTF8_FULL)
+                            .apply_modifier(UTF8_ROUND_CORNERS)
+                            .set_header(vec![
+                                "Index", "Number", "Nodes", "Special", "Color", "Machine", "Name",
+                            ]);
+                        for net in netlist {
+                            table.add_row(vec![
+                                net.index.to_string(),
+                                net.number.to_string(),
+                                net.nodes
+                                    .iter()
+                                    .map(|n| n.to_string())
+                                    .collect::<Vec<String>>()
+                                    .join(", "),
+                                net.special.to_string(),
+                                net.color.to_string(),
+                                net.machine.to_string(),
+                                net.name,
+                            ]);
                         }
-                        OutputFormat::Json => {
-                            serde_json::to_writer_pretty(&mut output, &netlist)?;
-                            output.write_all(b"\n")?;
+                        writeln!(&mut output, "{}", table)?;
+                    }
+                    NetOutputFormat::Json => {
+                        serde_json::to_writer_pretty(&mut output, &netlist)?;
+                        output.write_all(b"\n")?;
+                    }
+                    NetOutputFormat::Board => {
+                        let mut marks = HashMap::new();
+                        for net in &netlist {
+                            for node in &net.nodes {
+                                marks.insert(*node, (net.number.to_string(), net.color));
+                            }
                         }
+                        let legend = netlist
+                            .iter()
+                            .map(|net| (net.number.to_string(), net.color, net.name.clone()));
+                        write_board(&mut output, &marks, legend, color)?;
+                    }
+                    NetOutputFormat::Dot => {
+                        let bridgelist = board.bridgelist()?;
+                        output.write_all(graph::to_dot(&netlist, &bridgelist).as_bytes())?;
+                    }
+                    NetOutputFormat::Graphml => {
+                        let bridgelist = board.bridgelist()?;
+                        output.write_all(graph::to_graphml(&netlist, &bridgelist).as_bytes())?;
                     }
                 }
+            }
 
-                NetCommand::Send { file } => {
-                    let mut input = file_or_stdin(file)?;
-                    device.set_netlist(serde_json::from_reader(&mut input)?)?;
-                }
-            },
+            NetCommand::Send { file } => {
+                let mut input = file_or_stdin(file)?;
+                board.set_netlist(serde_json::from_reader(&mut input)?)?;
+            }
+        },
 
-            Command::Bridge(bridge_command) => match bridge_command {
-                BridgeCommand::List {
-                    file,
-                    output_format,
-                } => {
-                    let mut output = file_or_stdout(file)?;
-                    let bridgelist = device.bridgelist()?;
-                    match output_format {
-                        BridgeOutputFormat::List => {
-                            for (i, (a, b)) in bridgelist.into_iter().enumerate() {
-                                if i > 0 {
-                                    write!(&mut output, ",")?;
-                                }
-                                write!(&mut output, "{}-{}", a, b)?;
+        Command::Bridge(bridge_command) => match bridge_command {
+            BridgeCommand::List {
+                file,
+                output_format,
+            } => {
+                let color = file.is_none() && use_color();
+                let mut output = file_or_stdout(file)?;
+                let bridgelist = board.bridgelist()?;
+                match output_format {
+                    BridgeOutputFormat::List => {
+                        for (i, (a, b)) in bridgelist.into_iter().enumerate() {
+                            if i > 0 {
+                                write!(&mut output, ",")?;
                             }
-                            output.write_all(b"\n")?;
-                        }
-                        BridgeOutputFormat::Json => {
-                            serde_json::to_writer_pretty(&mut output, &bridgelist)?;
-                            output.write_all(b"\n")?;
+                            write!(&mut output, "{}-{}", a, b)?;
                         }
+                        output.write_all(b"\n")?;
                     }
-                }
-                BridgeCommand::Set { bridges, file } => {
-                    let source = match (bridges, file) {
-                        (None, None) => {
-                            return Err(anyhow::anyhow!(
-                                "Either `[bridges]` or `--file` must be given"
-                            ))
-                        }
-                        (Some(_), Some(_)) => {
-                            return Err(anyhow::anyhow!(
-                                "Cannot accept `[bridges]` together with `--file`"
-                            ))
+                    BridgeOutputFormat::Json => {
+                        serde_json::to_writer_pretty(&mut output, &bridgelist)?;
+                        output.write_all(b"\n")?;
+                    }
+                    BridgeOutputFormat::Board => {
+                        let groups = layout::bridge_groups(&bridgelist);
+                        let mut marks = HashMap::new();
+                        let mut legend = vec![];
+                        for (i, group) in groups.iter().enumerate() {
+                            let color = layout::PALETTE[i % layout::PALETTE.len()];
+                            for node in group {
+                                marks.insert(*node, ((i + 1).to_string(), color));
+                            }
+                            let nodes: Vec<String> =
+                                group.iter().map(|node| node.to_string()).collect();
+                            legend.push(((i + 1).to_string(), color, nodes.join(", ")));
                         }
-                        (Some(bridges), _) => bridges,
-                        (_, Some(file)) => std::fs::read_to_string(file)?,
-                    };
-
-                    let bridgelist = if source.starts_with('[') {
-                        serde_json::from_str(&source).expect("parse bridgelist as JSON")
-                    } else {
-                        let (_, bridgelist) =
-                            nom::combinator::all_consuming(parser::bridges)(&source)
-                                .expect("parse bridgelist");
-                        bridgelist
-                    };
-                    device.set_bridgelist(bridgelist)?;
+                        write_board(&mut output, &marks, legend, color)?;
+                    }
                 }
-                BridgeCommand::Add { bridges, file } => {
-                    let source = match (bridges, file) {
-                        (None, None) => {
-                            return Err(anyhow::anyhow!(
-                                "Either `[bridges]` or `--file` must be given"
-                            ))
-                        }
-                        (Some(_), Some(_)) => {
-                            return Err(anyhow::anyhow!(
-                                "Cannot accept `[bridges]` together with `--file`"
-                            ))
-                        }
-                        (Some(bridges), _) => bridges,
-                        (_, Some(file)) => std::fs::read_to_string(file)?,
-                    };
-
-                    let bridgelist = if source.starts_with('[') {
-                        serde_json::from_str(&source).expect("parse bridgelist as JSON")
-                    } else {
-                        let (_, bridgelist) =
-                            nom::combinator::all_consuming(parser::bridges)(&source)
-                                .expect("parse bridgelist");
-                        bridgelist
-                    };
-                    let mut combined = device.bridgelist()?;
-                    for bridge in bridgelist {
-                        if !combined.contains(&bridge) {
-                            combined.push(bridge);
-                        }
+            }
+            BridgeCommand::Set { bridges, file } => {
+                board.set_bridgelist(read_bridges(bridges, file)?)?;
+            }
+            BridgeCommand::Add { bridges, file } => {
+                board.add_bridges(read_bridges(bridges, file)?)?;
+            }
+            BridgeCommand::Remove { bridges, file } => {
+                board.remove_bridges(read_bridges(bridges, file)?)?;
+            }
+            BridgeCommand::Clear => {
+                board.set_bridgelist(vec![])?;
+            }
+        },
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "client")]
+fn run_history_command(client: &client::Client, command: HistoryCommand) -> anyhow::Result<()> {
+    let entry = match command {
+        HistoryCommand::List { output_format } => {
+            let history = client.history()?;
+            match output_format {
+                OutputFormat::Table => {
+                    let mut table = Table::new();
+                    table
+                        .load_preset(UTF8_FULL)
+                        .apply_modifier(UTF8_ROUND_CORNERS)
+                        .set_header(vec!["", "ID", "Time", "Cause", "Nets", "Bridges", "Supply"]);
+                    for entry in history.entries {
+                        let current = history.current == Some(entry.id);
+                        table.add_row(vec![
+                            if current { "*" } else { "" }.to_string(),
+                            entry.id.to_string(),
+                            entry.time,
+                            entry.cause,
+                            entry.state.netlist.len().to_string(),
+                            entry.state.bridgelist.len().to_string(),
+                            entry.state.supply_switch.to_string(),
+                        ]);
                     }
-                    device.set_bridgelist(combined)?;
+                    println!("{}", table);
                 }
-                BridgeCommand::Clear => {
-                    device.set_bridgelist(vec![])?;
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(std::io::stdout(), &history)?;
+                    println!();
                 }
-            },
-            _ => unreachable!(),
+            }
+            return Ok(());
+        }
+        HistoryCommand::Undo => client.undo()?,
+        HistoryCommand::Redo => client.redo()?,
+        HistoryCommand::Restore { id } => client.restore(id)?,
+    };
+    println!("Now at #{} ({})", entry.id, entry.cause);
+    Ok(())
+}
+
+#[cfg(feature = "client")]
+fn run_lease_command(client: &client::Client, command: LeaseCommand) -> anyhow::Result<()> {
+    match command {
+        LeaseCommand::Status => match client.lease()? {
+            Some(lease) => println!("Held by {} until {}", lease.holder, lease.expires_at),
+            None => println!("Not leased"),
+        },
+        LeaseCommand::Acquire { holder, duration } => {
+            let holder = holder
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_else(|| "jlctl".to_string());
+            let granted =
+                client.acquire_lease(&holder, std::time::Duration::from_secs(duration))?;
+            eprintln!(
+                "Leased to {} until {}. To make changes, run:",
+                granted.lease.holder, granted.lease.expires_at
+            );
+            eprintln!("    export JLCTL_LEASE={}", granted.token);
+            println!("{}", granted.token);
+        }
+        LeaseCommand::Release => {
+            let lease = client.release_lease()?;
+            println!("Released lease of {}", lease.holder);
         }
-        Ok(())
-    })?;
+    }
+    Ok(())
+}
 
+/// Where snapshots are stored and taken from
+enum Snapshots<'a> {
+    /// A running `jlctl server`
+    #[cfg(feature = "client")]
+    Remote(client::Client),
+    /// A local directory, and the device on the serial port
+    Local(
+        snapshot::Store,
+        &'a mut device_manager::DeviceManager<logger::FileLogger>,
+    ),
+}
+
+/// Run `f` with the snapshots of `remote` (a running `jlctl server`) if given,
+/// otherwise with the snapshots in `dir` and the device on the serial port.
+///
+/// The device is closed afterwards.
+fn with_snapshots<T>(
+    #[cfg(feature = "client")] remote: Option<client::Client>,
+    device_manager: &mut device_manager::DeviceManager<logger::FileLogger>,
+    dir: std::path::PathBuf,
+    f: impl FnOnce(&mut Snapshots) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let mut snapshots = Snapshots::Local(snapshot::Store::new(dir), device_manager);
+    #[cfg(feature = "client")]
+    if let Some(client) = remote {
+        snapshots = Snapshots::Remote(client);
+    }
+    let result = f(&mut snapshots);
+    drop(snapshots);
     device_manager.close_device();
+    result
+}
+
+impl Snapshots<'_> {
+    fn list(&mut self) -> anyhow::Result<Vec<snapshot::Summary>> {
+        match self {
+            #[cfg(feature = "client")]
+            Snapshots::Remote(client) => Ok(client.snapshots()?),
+            Snapshots::Local(store, _) => store.list(),
+        }
+    }
+
+    fn load(&mut self, name: &str) -> anyhow::Result<snapshot::Snapshot> {
+        match self {
+            #[cfg(feature = "client")]
+            Snapshots::Remote(client) => Ok(client.snapshot(name)?),
+            Snapshots::Local(store, _) => store.load(name),
+        }
+    }
+
+    /// Whether the lights of [`Snapshots::current`] are known.
+    ///
+    /// Only a server keeps the device open, and remembers which lights it has set.
+    fn knows_lights(&self) -> bool {
+        match self {
+            #[cfg(feature = "client")]
+            Snapshots::Remote(_) => true,
+            Snapshots::Local(..) => false,
+        }
+    }
+
+    /// Snapshot of the board's current state
+    fn current(&mut self) -> anyhow::Result<snapshot::Snapshot> {
+        match self {
+            #[cfg(feature = "client")]
+            Snapshots::Remote(client) => Ok(client.state()?),
+            Snapshots::Local(_, device_manager) => {
+                device_manager.with_device(snapshot::Snapshot::capture)
+            }
+        }
+    }
 
+    fn save(&mut self, name: &str) -> anyhow::Result<snapshot::Snapshot> {
+        match self {
+            #[cfg(feature = "client")]
+            Snapshots::Remote(client) => Ok(client.save_snapshot(name)?),
+            Snapshots::Local(store, device_manager) => {
+                let snapshot = device_manager.with_device(snapshot::Snapshot::capture)?;
+                store.save(name, &snapshot)?;
+                Ok(snapshot)
+            }
+        }
+    }
+
+    /// Restore a snapshot, returning the differences that remain
+    fn restore(&mut self, name: &str) -> anyhow::Result<Vec<diff::Change>> {
+        match self {
+            #[cfg(feature = "client")]
+            Snapshots::Remote(client) => Ok(client.restore_snapshot(name)?),
+            Snapshots::Local(store, device_manager) => {
+                let snapshot = store.load(name)?;
+                device_manager.with_device(|device| snapshot.restore(device))
+            }
+        }
+    }
+}
+
+fn run_snapshot_command(snapshots: &mut Snapshots, command: SnapshotCommand) -> anyhow::Result<()> {
+    match command {
+        SnapshotCommand::Save { name } => {
+            let snapshot = snapshots.save(&name)?;
+            println!(
+                "Saved snapshot {:?} ({} nets, {} bridges)",
+                name,
+                snapshot.state.netlist.len(),
+                snapshot.state.bridgelist.len()
+            );
+        }
+        SnapshotCommand::Restore { name } => {
+            let mismatches = snapshots.restore(&name)?;
+            if !mismatches.is_empty() {
+                eprintln!("The board differs from the snapshot after restoring it:");
+                for change in &mismatches {
+                    eprintln!("  {}", change);
+                }
+                return Err(anyhow::anyhow!(
+                    "Snapshot {:?} was not fully restored",
+                    name
+                ));
+            }
+            println!("Restored snapshot {:?}", name);
+        }
+        SnapshotCommand::List { output_format } => {
+            let summaries = snapshots.list()?;
+            match output_format {
+                OutputFormat::Table => {
+                    let mut table = Table::new();
+                    table
+                        .load_preset(UTF8_FULL)
+                        .apply_modifier(UTF8_ROUND_CORNERS)
+                        .set_header(vec!["Name", "Created", "Nets", "Bridges", "Supply"]);
+                    for summary in summaries {
+                        table.add_row(vec![
+                            summary.name,
+                            summary.created,
+                            summary.nets.to_string(),
+                            summary.bridges.to_string(),
+                            summary.supply_switch.to_string(),
+                        ]);
+                    }
+                    println!("{}", table);
+                }
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(std::io::stdout(), &summaries)?;
+                    println!();
+                }
+            }
+        }
+        SnapshotCommand::Diff {
+            from,
+            to,
+            output_format,
+        } => {
+            let old = snapshots.load(&from)?;
+            let changes = match &to {
+                Some(to) => old.diff(&snapshots.load(to)?),
+                None if snapshots.knows_lights() => old.diff(&snapshots.current()?),
+                None => diff::states(&old.state, &snapshots.current()?.state),
+            };
+            print_changes(&changes, output_format)?;
+        }
+    }
     Ok(())
 }
 
+/// Compare two sources, as given to `jlctl diff`
+fn diff_sources(
+    snapshots: &mut Snapshots,
+    from: &str,
+    to: &str,
+) -> anyhow::Result<Vec<diff::Change>> {
+    let old = load_source(snapshots, from)?;
+    let new = load_source(snapshots, to)?;
+    anyhow::ensure!(
+        old.overlaps(&new),
+        "Nothing to compare: {:?} and {:?} have no part (netlist, bridges or supply switch) in common",
+        from,
+        to
+    );
+    Ok(diff::partial(&old, &new))
+}
+
+/// Load a state from `board`, `snapshot:<name>` or a file
+fn load_source(snapshots: &mut Snapshots, source: &str) -> anyhow::Result<diff::PartialState> {
+    if source == "board" {
+        return Ok(snapshots.current()?.state.into());
+    }
+    if let Some(name) = source.strip_prefix("snapshot:") {
+        return Ok(snapshots.load(name)?.state.into());
+    }
+    let input =
+        std::fs::read_to_string(source).with_context(|| format!("Failed to read {:?}", source))?;
+    diff::PartialState::parse(&input).with_context(|| format!("Failed to parse {:?}", source))
+}
+
+/// Draw the board, followed by a legend of the marks
+fn write_board(
+    output: &mut dyn std::io::Write,
+    marks: &HashMap<types::Node, (String, types::Color)>,
+    legend: impl IntoIterator<Item = (String, types::Color, String)>,
+    color: bool,
+) -> anyhow::Result<()> {
+    for line in layout::draw_board(marks, color) {
+        writeln!(output, "{}", line.trim_end())?;
+    }
+    writeln!(output)?;
+    for (mark, mark_color, description) in legend {
+        if color {
+            let painted = format!("{} {:>2} \x1b[0m", layout::paint(mark_color), mark);
+            writeln!(output, "{} {}", painted, description)?;
+        } else {
+            writeln!(output, "{:>3}  {}", mark, description)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether to color output on stdout: only for terminals, unless `NO_COLOR` is set
+fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Print changes one per line (colored, when writing to a terminal), or as JSON
+fn print_changes(changes: &[diff::Change], output_format: DiffOutputFormat) -> anyhow::Result<()> {
+    match output_format {
+        DiffOutputFormat::Text if changes.is_empty() => println!("No differences"),
+        DiffOutputFormat::Text => {
+            let color = use_color();
+            for change in changes {
+                let line = change.to_string();
+                if !color {
+                    println!("{}", line);
+                    continue;
+                }
+                let code = match line.chars().next() {
+                    Some('+') => 32, // green
+                    Some('-') => 31, // red
+                    _ => 33,         // yellow
+                };
+                println!("\x1b[{}m{}\x1b[0m", code, line);
+            }
+        }
+        DiffOutputFormat::Json => {
+            serde_json::to_writer_pretty(std::io::stdout(), changes)?;
+            println!();
+        }
+    }
+    Ok(())
+}
+
+/// Format status values of a chip's X or Y lines, as reported by the device
+fn format_status(status: &[i8]) -> String {
+    status
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Read bridges from either the command line, or the given file
+fn read_bridges(bridges: Option<String>, file: Option<String>) -> anyhow::Result<Bridgelist> {
+    let source = match (bridges, file) {
+        (None, None) => {
+            return Err(anyhow::anyhow!(
+                "Either `[bridges]` or `--file` must be given"
+            ))
+        }
+        (Some(_), Some(_)) => {
+            return Err(anyhow::anyhow!(
+                "Cannot accept `[bridges]` together with `--file`"
+            ))
+        }
+        (Some(bridges), _) => bridges,
+        (_, Some(file)) => std::fs::read_to_string(file)?,
+    };
+    parser::bridgelist_input(&source)
+}
+
 fn file_or_stdout(file_path: Option<String>) -> std::io::Result<Box<dyn std::io::Write>> {
     Ok(match file_path {
         Some(file_path) => {
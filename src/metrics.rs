@@ -0,0 +1,269 @@
+//! Counters and histograms describing the communication with the device, and the HTTP server.
+//!
+//! Metrics are process-wide (see [`METRICS`]), so they can be recorded from anywhere, without
+//! passing a registry around. The server exposes them in Prometheus text format at `GET /metrics`.
+
+use std::collections::BTreeMap;
+#[cfg(feature = "server")]
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the buckets used for latency histograms
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Metrics of this process
+pub static METRICS: Metrics = Metrics::new();
+
+pub struct Metrics {
+    /// Keyed by (route, method, status)
+    #[cfg(feature = "server")]
+    http_requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    /// Time from sending an instruction until the device acknowledged it, keyed by instruction
+    device_roundtrip: Mutex<BTreeMap<&'static str, Histogram>>,
+    /// Instructions the device did not respond to in time, keyed by instruction
+    device_timeouts: Mutex<BTreeMap<&'static str, u64>>,
+    /// Requests that failed, because they were waiting for the device for too long
+    #[cfg(feature = "server")]
+    request_timeouts: AtomicU64,
+    reconnects: AtomicU64,
+    unrecognized_lines: AtomicU64,
+    connected: AtomicBool,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Number of observations per bucket (not cumulative)
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            #[cfg(feature = "server")]
+            http_requests: Mutex::new(BTreeMap::new()),
+            device_roundtrip: Mutex::new(BTreeMap::new()),
+            device_timeouts: Mutex::new(BTreeMap::new()),
+            #[cfg(feature = "server")]
+            request_timeouts: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            unrecognized_lines: AtomicU64::new(0),
+            connected: AtomicBool::new(false),
+        }
+    }
+
+    /// Count an HTTP request. `route` is the matched pattern (e.g. `/nets/{index}`), not the actual path.
+    #[cfg(feature = "server")]
+    pub fn http_request(&self, route: &str, method: &str, status: u16) {
+        let mut requests = self.http_requests.lock().unwrap();
+        *requests
+            .entry((route.to_string(), method.to_string(), status))
+            .or_default() += 1;
+    }
+
+    pub fn device_roundtrip(&self, instruction: &'static str, duration: Duration) {
+        let mut roundtrip = self.device_roundtrip.lock().unwrap();
+        roundtrip
+            .entry(instruction)
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn device_timeout(&self, instruction: &'static str) {
+        *self
+            .device_timeouts
+            .lock()
+            .unwrap()
+            .entry(instruction)
+            .or_default() += 1;
+    }
+
+    #[cfg(feature = "server")]
+    pub fn request_timeout(&self) {
+        self.request_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn unrecognized_line(&self) {
+        self.unrecognized_lines.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    #[cfg(feature = "server")]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "jlctl_http_requests_total",
+            "counter",
+            "HTTP requests, by route, method and status",
+        );
+        for ((route, method, status), count) in self.http_requests.lock().unwrap().iter() {
+            _ = writeln!(
+                out,
+                "jlctl_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                escape(route),
+                escape(method),
+                status,
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "jlctl_device_roundtrip_seconds",
+            "histogram",
+            "Time until the device acknowledged an instruction",
+        );
+        for (instruction, histogram) in self.device_roundtrip.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                _ = writeln!(
+                    out,
+                    "jlctl_device_roundtrip_seconds_bucket{{instruction=\"{}\",le=\"{}\"}} {}",
+                    instruction, bound, cumulative
+                );
+            }
+            _ = writeln!(
+                out,
+                "jlctl_device_roundtrip_seconds_bucket{{instruction=\"{}\",le=\"+Inf\"}} {}",
+                instruction, histogram.count
+            );
+            _ = writeln!(
+                out,
+                "jlctl_device_roundtrip_seconds_sum{{instruction=\"{}\"}} {}",
+                instruction, histogram.sum
+            );
+            _ = writeln!(
+                out,
+                "jlctl_device_roundtrip_seconds_count{{instruction=\"{}\"}} {}",
+                instruction, histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "jlctl_device_timeouts_total",
+            "counter",
+            "Instructions the device did not respond to in time",
+        );
+        for (instruction, count) in self.device_timeouts.lock().unwrap().iter() {
+            _ = writeln!(
+                out,
+                "jlctl_device_timeouts_total{{instruction=\"{}\"}} {}",
+                instruction, count
+            );
+        }
+
+        let simple = [
+            (
+                "jlctl_request_timeouts_total",
+                "counter",
+                "Requests that failed while waiting for the device",
+                self.request_timeouts.load(Ordering::Relaxed),
+            ),
+            (
+                "jlctl_device_reconnects_total",
+                "counter",
+                "Times the device was opened again, after the connection was lost",
+                self.reconnects.load(Ordering::Relaxed),
+            ),
+            (
+                "jlctl_device_unrecognized_lines_total",
+                "counter",
+                "Lines received from the device that could not be parsed",
+                self.unrecognized_lines.load(Ordering::Relaxed),
+            ),
+            (
+                "jlctl_device_connected",
+                "gauge",
+                "Whether the device is currently connected",
+                self.connected.load(Ordering::Relaxed) as u64,
+            ),
+        ];
+        for (name, kind, help, value) in simple {
+            header(&mut out, name, kind, help);
+            _ = writeln!(out, "{} {}", name, value);
+        }
+
+        out
+    }
+}
+
+#[cfg(feature = "server")]
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    _ = writeln!(out, "# HELP {} {}", name, help);
+    _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value
+#[cfg(feature = "server")]
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.http_request("/nets/{index}", "GET", 200);
+        metrics.http_request("/nets/{index}", "GET", 200);
+        metrics.device_roundtrip("GetNetlist", Duration::from_millis(30));
+        metrics.device_roundtrip("GetNetlist", Duration::from_secs(20));
+        metrics.device_timeout("GetBridgelist");
+        metrics.set_connected(true);
+
+        let out = metrics.render();
+        assert!(out.contains(
+            "jlctl_http_requests_total{route=\"/nets/{index}\",method=\"GET\",status=\"200\"} 2\n"
+        ));
+        assert!(out.contains(
+            "jlctl_device_roundtrip_seconds_bucket{instruction=\"GetNetlist\",le=\"0.025\"} 0\n"
+        ));
+        assert!(out.contains(
+            "jlctl_device_roundtrip_seconds_bucket{instruction=\"GetNetlist\",le=\"0.05\"} 1\n"
+        ));
+        assert!(out.contains(
+            "jlctl_device_roundtrip_seconds_bucket{instruction=\"GetNetlist\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(out.contains("jlctl_device_timeouts_total{instruction=\"GetBridgelist\"} 1\n"));
+        assert!(out.contains("jlctl_device_connected 1\n"));
+        assert!(out.contains("jlctl_device_reconnects_total 0\n"));
+    }
+}
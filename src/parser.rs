@@ -157,6 +157,20 @@ fn bridgelist(input: &str) -> IResult<&str, Bridgelist> {
     )(input)
 }
 
+/// Parse a list of bridges given by the user.
+///
+/// Accepts either a JSON array of pairs, or the text form (e.g. `GND-17,12-23`).
+pub fn bridgelist_input(source: &str) -> anyhow::Result<Bridgelist> {
+    let source = source.trim();
+    if source.starts_with('[') {
+        Ok(serde_json::from_str(source)?)
+    } else {
+        let (_, bridgelist) = all_consuming(bridges)(source)
+            .map_err(|e| anyhow::anyhow!("Failed to parse bridges: {:?}", e))?;
+        Ok(bridgelist)
+    }
+}
+
 pub fn bridges(input: &str) -> IResult<&str, Bridgelist> {
     separated_list0(tag(","), bridge)(input)
 }
@@ -265,6 +279,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_bridgelist_input() {
+        use Node::*;
+
+        let expected = vec![(GND, Column(17)), (Column(12), NANO_D3)];
+        assert_eq!(bridgelist_input("GND-17,12-D3\n").unwrap(), expected);
+        assert_eq!(
+            bridgelist_input(r#"[["GND", 17], [12, "NANO_D3"]]"#).unwrap(),
+            expected
+        );
+        assert!(bridgelist_input("GND-17,").is_err());
+    }
+
     const INITIAL_NETLIST: [&str; 9] = [
         "::netlist-begin",
         "::net[1,1,GND,true,001c04,false,GND]",
@@ -0,0 +1,622 @@
+//! Images of the board state, as SVG or PNG.
+//!
+//! The board is first drawn as a list of [`Shape`]s, which are then either written as SVG, or rasterized for PNG.
+//! PNG images use a built-in bitmap font, so they look the same on every machine.
+
+use crate::{
+    layout,
+    types::{Color, Net, Node},
+};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::Write;
+
+const MARGIN: f32 = 20.0;
+/// Left edge of the nodes, leaving space for the row names
+const LEFT: f32 = 80.0;
+/// Distance between breadboard columns
+const PITCH: f32 = 24.0;
+/// Width of a legend entry
+const LEGEND_WIDTH: f32 = 190.0;
+/// Largest `scale` for [`Drawing::to_png`]. The board alone then takes about 70 MB of pixels.
+pub const MAX_SCALE: u32 = 8;
+
+const BACKGROUND: Color = Color([255, 255, 255]);
+const TEXT: Color = Color([34, 34, 34]);
+const HOLE: Color = Color([51, 51, 51]);
+/// Fill of nodes that are not connected to anything
+const UNUSED: Color = Color([221, 221, 221]);
+const NOTE: Color = Color([255, 250, 220]);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Start,
+    Middle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        radius: f32,
+        fill: Color,
+    },
+    Circle {
+        x: f32,
+        y: f32,
+        radius: f32,
+        fill: Color,
+    },
+    /// Quadratic curve from `from` to `to`, pulled towards `control`
+    Curve {
+        from: (f32, f32),
+        control: (f32, f32),
+        to: (f32, f32),
+        width: f32,
+        color: Color,
+    },
+    /// Single line of text, vertically centered on `y`
+    Text {
+        x: f32,
+        y: f32,
+        size: f32,
+        text: String,
+        color: Color,
+        anchor: Anchor,
+    },
+}
+
+/// What to draw, besides the nets and bridges
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Drawn above the board
+    pub title: Option<String>,
+    /// Notes drawn next to nodes
+    pub annotations: Vec<(Node, String)>,
+}
+
+/// A drawing of the board
+#[derive(Debug, Clone)]
+pub struct Drawing {
+    pub width: f32,
+    pub height: f32,
+    pub shapes: Vec<Shape>,
+}
+
+/// Draw the board with the nodes in the color of their nets, and bridges as arcs between their ends.
+///
+/// Without a netlist (e.g. when drawing a file of bridges), each group of bridged nodes gets a color of its own.
+pub fn draw(netlist: Option<&[Net]>, bridgelist: &[(Node, Node)], options: &Options) -> Drawing {
+    // color of each node, and the legend: color and description
+    let mut colors = HashMap::new();
+    let mut legend = vec![];
+    match netlist {
+        Some(netlist) => {
+            for net in netlist {
+                for node in &net.nodes {
+                    colors.insert(*node, net.color);
+                }
+                legend.push((net.color, format!("{} {}", net.number, net.name)));
+            }
+        }
+        None => {
+            for (i, group) in layout::bridge_groups(bridgelist).iter().enumerate() {
+                let color = layout::PALETTE[i % layout::PALETTE.len()];
+                for node in group {
+                    colors.insert(*node, color);
+                }
+                let nodes: Vec<String> = group.iter().map(|node| node.to_string()).collect();
+                legend.push((color, format!("{}: {}", i + 1, nodes.join(", "))));
+            }
+        }
+    }
+    let color_of = |node: &Node| colors.get(node).copied().unwrap_or(UNUSED);
+
+    let width = LEFT + 30.0 * PITCH + MARGIN;
+    let mut shapes = vec![];
+    let mut positions = HashMap::new();
+    let mut y = MARGIN;
+
+    if let Some(title) = &options.title {
+        shapes.push(text(MARGIN, y + 8.0, 16.0, title, Anchor::Start));
+        y += 32.0;
+    }
+
+    for row in layout::rows() {
+        let pitch = PITCH * row.cell_width() as f32 / 3.0;
+        let columns = row.cell_width() == 3;
+        let center = if columns { y + 47.0 } else { y + 10.0 };
+        shapes.push(text(MARGIN, center, 12.0, row.name, Anchor::Start));
+        for (index, node) in row.nodes.iter().enumerate() {
+            let x = LEFT + pitch * (index as f32 + 0.5);
+            positions.insert(*node, (x, center));
+            if columns {
+                // the column number above a strip of 5 connected holes
+                shapes.push(text(x, y + 8.0, 9.0, &layout::label(*node), Anchor::Middle));
+                shapes.push(Shape::Rect {
+                    x: x - 9.0,
+                    y: y + 16.0,
+                    width: 18.0,
+                    height: 62.0,
+                    radius: 4.0,
+                    fill: color_of(node),
+                });
+                for hole in 0..5 {
+                    shapes.push(Shape::Circle {
+                        x,
+                        y: y + 23.0 + 12.0 * hole as f32,
+                        radius: 3.0,
+                        fill: HOLE,
+                    });
+                }
+            } else {
+                // a pin, with its name below
+                shapes.push(Shape::Circle {
+                    x,
+                    y: center,
+                    radius: 9.0,
+                    fill: color_of(node),
+                });
+                shapes.push(Shape::Circle {
+                    x,
+                    y: center,
+                    radius: 3.0,
+                    fill: HOLE,
+                });
+                shapes.push(text(
+                    x,
+                    y + 28.0,
+                    9.0,
+                    &layout::label(*node),
+                    Anchor::Middle,
+                ));
+            }
+        }
+        y += if columns { 96.0 } else { 44.0 };
+    }
+
+    for (a, b) in bridgelist {
+        let (Some(&from), Some(&to)) = (positions.get(a), positions.get(b)) else {
+            continue;
+        };
+        if from == to {
+            continue;
+        }
+        let control = bulge(from, to);
+        shapes.push(Shape::Curve {
+            from,
+            control,
+            to,
+            width: 5.0,
+            color: HOLE,
+        });
+        shapes.push(Shape::Curve {
+            from,
+            control,
+            to,
+            width: 3.0,
+            color: colors.get(a).copied().unwrap_or(UNUSED),
+        });
+    }
+
+    // legend, in as many columns as fit
+    let per_line = ((width - 2.0 * MARGIN) / LEGEND_WIDTH).floor().max(1.0) as usize;
+    for (i, (color, description)) in legend.iter().enumerate() {
+        let x = MARGIN + LEGEND_WIDTH * (i % per_line) as f32;
+        let y = y + 8.0 + 22.0 * (i / per_line) as f32;
+        shapes.push(Shape::Rect {
+            x,
+            y,
+            width: 14.0,
+            height: 14.0,
+            radius: 2.0,
+            fill: *color,
+        });
+        shapes.push(text(x + 20.0, y + 7.0, 11.0, description, Anchor::Start));
+    }
+    y += 8.0 + 22.0 * legend.len().div_ceil(per_line) as f32;
+
+    // notes go on top of everything else
+    for (node, note) in &options.annotations {
+        let Some(&(x, node_y)) = positions.get(node) else {
+            continue;
+        };
+        let (x, note_y) = (x + 8.0, node_y - 20.0);
+        let note_width = note.chars().count() as f32 * 7.5 + 8.0;
+        shapes.push(Shape::Rect {
+            x: x - 1.0,
+            y: note_y - 9.0,
+            width: note_width + 2.0,
+            height: 18.0,
+            radius: 3.0,
+            fill: HOLE,
+        });
+        shapes.push(Shape::Rect {
+            x,
+            y: note_y - 8.0,
+            width: note_width,
+            height: 16.0,
+            radius: 2.0,
+            fill: NOTE,
+        });
+        shapes.push(text(x + 4.0, note_y, 10.0, note, Anchor::Start));
+    }
+
+    Drawing {
+        width,
+        height: y + MARGIN,
+        shapes,
+    }
+}
+
+fn text(x: f32, y: f32, size: f32, text: &str, anchor: Anchor) -> Shape {
+    Shape::Text {
+        x,
+        y,
+        size,
+        text: text.to_string(),
+        color: TEXT,
+        anchor,
+    }
+}
+
+/// Control point for an arc between two nodes, bending upwards (or to the right, for vertical arcs)
+fn bulge((x1, y1): (f32, f32), (x2, y2): (f32, f32)) -> (f32, f32) {
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let length = (dx * dx + dy * dy).sqrt();
+    let (mut nx, mut ny) = (dy / length, -dx / length);
+    if ny > 0.0 || (ny == 0.0 && nx < 0.0) {
+        (nx, ny) = (-nx, -ny);
+    }
+    let offset = 0.2 * length + 12.0;
+    ((x1 + x2) / 2.0 + nx * offset, (y1 + y2) / 2.0 + ny * offset)
+}
+
+impl Drawing {
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" font-family=\"monospace\">\n",
+            self.width, self.height
+        );
+        svg.push_str(&format!(
+            "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+            BACKGROUND
+        ));
+        for shape in &self.shapes {
+            let element = match shape {
+                Shape::Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                    radius,
+                    fill,
+                } => format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\" fill=\"{}\"/>",
+                    x, y, width, height, radius, fill
+                ),
+                Shape::Circle { x, y, radius, fill } => format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+                    x, y, radius, fill
+                ),
+                Shape::Curve {
+                    from,
+                    control,
+                    to,
+                    width,
+                    color,
+                } => format!(
+                    "<path d=\"M {} {} Q {} {} {} {}\" stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\" fill=\"none\"/>",
+                    from.0, from.1, control.0, control.1, to.0, to.1, color, width
+                ),
+                Shape::Text {
+                    x,
+                    y,
+                    size,
+                    text,
+                    color,
+                    anchor,
+                } => format!(
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" fill=\"{}\" text-anchor=\"{}\" dominant-baseline=\"central\">{}</text>",
+                    x,
+                    y,
+                    size,
+                    color,
+                    match anchor {
+                        Anchor::Start => "start",
+                        Anchor::Middle => "middle",
+                    },
+                    xml_escape(text)
+                ),
+            };
+            svg.push_str(&element);
+            svg.push('\n');
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Rasterize the drawing, with `scale` (1 to [`MAX_SCALE`]) pixels per unit
+    pub fn to_png(&self, scale: u32) -> Result<Vec<u8>> {
+        anyhow::ensure!(
+            (1..=MAX_SCALE).contains(&scale),
+            "Scale must be between 1 and {}",
+            MAX_SCALE
+        );
+        let scale = scale as f32;
+        let mut canvas = Canvas::new(
+            (self.width * scale).ceil() as usize,
+            (self.height * scale).ceil() as usize,
+        );
+        for shape in &self.shapes {
+            match shape {
+                Shape::Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                    radius,
+                    fill,
+                } => canvas.fill_rect(
+                    x * scale,
+                    y * scale,
+                    width * scale,
+                    height * scale,
+                    radius * scale,
+                    *fill,
+                ),
+                Shape::Circle { x, y, radius, fill } => {
+                    canvas.fill_circle(x * scale, y * scale, radius * scale, *fill)
+                }
+                Shape::Curve {
+                    from,
+                    control,
+                    to,
+                    width,
+                    color,
+                } => {
+                    let point = |t: f32| {
+                        let u = 1.0 - t;
+                        (
+                            scale * (u * u * from.0 + 2.0 * u * t * control.0 + t * t * to.0),
+                            scale * (u * u * from.1 + 2.0 * u * t * control.1 + t * t * to.1),
+                        )
+                    };
+                    let radius = width * scale / 2.0;
+                    let steps = 200;
+                    for step in 0..=steps {
+                        let (x, y) = point(step as f32 / steps as f32);
+                        canvas.fill_circle(x, y, radius, *color);
+                    }
+                }
+                Shape::Text {
+                    x,
+                    y,
+                    size,
+                    text,
+                    color,
+                    anchor,
+                } => canvas.text(x * scale, y * scale, size * scale, text, *color, *anchor),
+            }
+        }
+        canvas.encode_png()
+    }
+}
+
+/// Escape text for use in XML (SVG, GraphML)
+pub(crate) fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// RGB pixels, drawn on without anti-aliasing
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        let Color(background) = BACKGROUND;
+        Self {
+            width,
+            height,
+            pixels: background.repeat(width * height),
+        }
+    }
+
+    /// Fill the pixels whose centers are inside the area, given as bounding box and predicate
+    fn fill(
+        &mut self,
+        (x0, y0, x1, y1): (f32, f32, f32, f32),
+        color: Color,
+        inside: impl Fn(f32, f32) -> bool,
+    ) {
+        let clamp_x = |x: f32| (x.max(0.0) as usize).min(self.width);
+        let clamp_y = |y: f32| (y.max(0.0) as usize).min(self.height);
+        for py in clamp_y(y0.floor())..clamp_y(y1.ceil()) {
+            for px in clamp_x(x0.floor())..clamp_x(x1.ceil()) {
+                if inside(px as f32 + 0.5, py as f32 + 0.5) {
+                    let offset = (py * self.width + px) * 3;
+                    self.pixels[offset..offset + 3].copy_from_slice(&color.0);
+                }
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, radius: f32, color: Color) {
+        let radius = radius.min(width / 2.0).min(height / 2.0);
+        self.fill((x, y, x + width, y + height), color, |px, py| {
+            // distance to the rectangle, shrunk by the radius of the corners
+            let dx = px.clamp(x + radius, x + width - radius) - px;
+            let dy = py.clamp(y + radius, y + height - radius) - py;
+            px >= x && px <= x + width && dx * dx + dy * dy <= radius * radius + f32::EPSILON
+        });
+    }
+
+    fn fill_circle(&mut self, x: f32, y: f32, radius: f32, color: Color) {
+        self.fill(
+            (x - radius, y - radius, x + radius, y + radius),
+            color,
+            |px, py| (px - x) * (px - x) + (py - y) * (py - y) <= radius * radius,
+        );
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, text: &str, color: Color, anchor: Anchor) {
+        // glyphs are 5x7 dots, plus one dot of spacing on each axis
+        let dot = (size / 8.0).max(1.0);
+        let width = text.chars().count() as f32 * 6.0 * dot - dot;
+        let left = match anchor {
+            Anchor::Start => x,
+            Anchor::Middle => x - width / 2.0,
+        };
+        let top = y - 3.5 * dot;
+        for (i, c) in text.chars().enumerate() {
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..5 {
+                    if bits & (0x10 >> column) != 0 {
+                        let dot_x = left + (i * 6 + column) as f32 * dot;
+                        let dot_y = top + row as f32 * dot;
+                        self.fill_rect(dot_x, dot_y, dot, dot, 0.0, color);
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>> {
+        // each line of pixels is preceded by its filter type (0: none)
+        let mut data = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for line in self.pixels.chunks(self.width * 3) {
+            data.push(0);
+            data.extend_from_slice(line);
+        }
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&data)?;
+        let data = encoder.finish()?;
+
+        let mut header = vec![];
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, default compression, filtering and no interlacing
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, chunk) in [(b"IHDR", &header), (b"IDAT", &data), (b"IEND", &vec![])] {
+            png.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            png.extend_from_slice(kind);
+            png.extend_from_slice(chunk);
+            let mut crc = flate2::Crc::new();
+            crc.update(kind);
+            crc.update(chunk);
+            png.extend_from_slice(&crc.sum().to_be_bytes());
+        }
+        Ok(png)
+    }
+}
+
+/// Rows of the 5x7 glyph for the character, with the leftmost dot in bit 4.
+/// Lowercase letters are drawn as uppercase, unknown characters as `?`.
+fn glyph(c: char) -> [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .find(|(glyph, _)| *glyph == c)
+        .or_else(|| FONT.iter().find(|(glyph, _)| *glyph == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap_or_default()
+}
+
+const FONT: &[(char, [u8; 7])] = &[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('\'', [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw() {
+        let bridges = [
+            (Node::Column(3), Node::Column(7)),
+            (Node::GND, Node::Column(17)),
+        ];
+        let options = Options {
+            title: Some("Blinky".to_string()),
+            annotations: vec![(Node::Column(3), "LED <anode>".to_string())],
+        };
+        let drawing = draw(None, &bridges, &options);
+
+        let svg = drawing.to_svg();
+        // each bridge is an outline and a colored arc
+        assert_eq!(svg.matches("<path").count(), 4);
+        assert!(svg.contains(">LED &lt;anode&gt;</text>"));
+        assert!(svg.contains(">1: 3, 7</text>"));
+
+        assert!(drawing.to_png(0).is_err());
+        assert!(drawing.to_png(MAX_SCALE + 1).is_err());
+        let png = drawing.to_png(1).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(
+            u32::from_be_bytes(png[16..20].try_into().unwrap()),
+            drawing.width.ceil() as u32
+        );
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
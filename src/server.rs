@@ -1,261 +1,1151 @@
 use crate::{
-    device_manager::DeviceManager,
+    device::Device,
+    device_manager::{DeviceManager, Status},
+    diff::Change,
+    graph,
     logger::FileLogger,
-    types::{Net, SupplySwitchPos},
+    metrics::METRICS,
+    parser,
+    snapshot::{Snapshot, Store},
+    state::BoardState,
+    types::{Color, Message, Net, NetPatch, NewNet, SupplySwitchPos},
     validate,
 };
 use actix_cors::Cors;
 use actix_web::{
-    get, http,
-    middleware::{Logger, NormalizePath},
-    post, put, web, App, HttpResponse, HttpServer, Responder, ResponseError, Result,
+    delete, dev::Service, get, http, middleware::NormalizePath, patch, post, put, web, App,
+    HttpRequest, HttpResponse, HttpServer, Responder, ResponseError,
 };
-use log::info;
-use serde_json::json;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
 use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use utoipa::ToSchema;
 
-#[cfg(feature = "jumperlab")]
+mod actor;
+mod auth;
+mod error;
+mod events;
+mod history;
 mod jumperlab;
+mod lease;
+mod openapi;
+#[cfg(feature = "tls")]
+mod tls;
+
+use actor::DeviceActor;
+use auth::Origins;
+pub use auth::{Auth, Role};
+use error::{Error, ErrorCode};
+use events::{Event, Events};
+use history::{Entry, History};
+use lease::{LeaseInfo, LeaseRequest, Leases};
+#[cfg(feature = "tls")]
+pub use tls::Tls;
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Options for the HTTP server
+pub struct Config {
+    /// Address to listen on: `<host>:<port>`, or `unix:<path>` for a Unix domain socket. Defaults to `localhost:0`.
+    pub listen_address: Option<String>,
+    /// Permissions of the socket file, when listening on a Unix domain socket
+    #[cfg(unix)]
+    pub socket_mode: u32,
+    /// Interval in which the device is polled for changes, while clients are subscribed to `/events`.
+    /// Polling is disabled if this is `None`.
+    pub poll_interval: Option<Duration>,
+    /// Allow sending raw instructions via `POST /raw`
+    pub allow_raw: bool,
+    /// Maximum time a request may wait for the device, before failing with status 504
+    pub request_timeout: Duration,
+    /// Credentials that clients must present. Authentication is disabled if none are configured.
+    pub auth: Auth,
+    /// Origins allowed to make cross-origin requests. If empty, any `http://localhost:<port>` origin is allowed.
+    /// Writes from browser pages on other origins are rejected.
+    pub allowed_origins: Vec<String>,
+    /// Serve via HTTPS, using the given certificate
+    #[cfg(feature = "tls")]
+    pub tls: Option<Tls>,
+    /// Serve jumperlab from this directory, instead of the embedded copy, and reload it when files change
+    pub ui_dir: Option<PathBuf>,
+    /// Number of board states kept for undo / redo
+    pub history_size: usize,
+    /// Longest duration a lease can be acquired for
+    pub max_lease: Duration,
+    /// Directory where named snapshots are stored
+    pub snapshot_dir: PathBuf,
+}
 
 struct Shared {
-    device_manager: Arc<Mutex<DeviceManager<FileLogger>>>,
+    device: DeviceActor,
+    events: Arc<Events>,
+    history: Arc<Mutex<History>>,
+    leases: Arc<Leases>,
+    snapshots: Store,
+    /// Origins allowed to connect to `/events`, besides the server's own
+    origins: Arc<Origins>,
+    allow_raw: bool,
+}
+
+/// Which entry of the [`History`] to return to
+enum Travel {
+    Undo,
+    Redo,
+    Restore(u64),
 }
 
 impl Shared {
-    fn netlist(&self) -> Result<Vec<Net>> {
+    /// Run the given closure with the device (on the device actor), and update connection status based on the result.
+    async fn with_device<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Device<FileLogger>, &Events) -> anyhow::Result<T> + Send + 'static,
+    {
+        let events = Arc::clone(&self.events);
         Ok(self
-            .device_manager
-            .lock()
-            .unwrap()
-            .with_device(|device| device.netlist())
-            .map_err(Error)?)
+            .device
+            .run(move |device_manager| {
+                let result = device_manager.with_device(|device| f(device, &events));
+                events.set_connected(result.is_ok());
+                result
+            })
+            .await??)
     }
-}
 
-#[derive(Debug)]
-struct Error(anyhow::Error);
+    async fn netlist(&self) -> Result<Vec<Net>> {
+        self.with_device(|device, _| device.netlist()).await
+    }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+    /// Make a change to the board, and record the resulting state in the history.
+    ///
+    /// Returns the result of `f`, along with the resulting state.
+    async fn change<T, F>(&self, cause: &'static str, f: F) -> Result<(T, BoardState)>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Device<FileLogger>, &Events) -> anyhow::Result<T> + Send + 'static,
+    {
+        let history = Arc::clone(&self.history);
+        self.with_device(move |device, events| {
+            let before = BoardState::read(device)?;
+            history.lock().unwrap().observe(before);
+            let result = f(device, events)?;
+            let state = events.sync(device)?;
+            history.lock().unwrap().record(cause, state.clone());
+            Ok((result, state))
+        })
+        .await
     }
-}
 
-impl ResponseError for Error {
-    fn error_response(&self) -> HttpResponse {
-        HttpResponse::BadGateway().json(json!({ "error": self.0.to_string() }))
+    /// Modify the netlist on the device.
+    ///
+    /// Reading, modifying and writing back the netlist all happens within a single job on the device actor,
+    /// so concurrent requests cannot interfere. Returns the resulting netlist.
+    async fn update_netlist<F>(&self, cause: &'static str, f: F) -> Result<Vec<Net>>
+    where
+        F: FnOnce(&mut Vec<Net>) -> Result<()> + Send + 'static,
+    {
+        // errors from modification or validation are kept separate, since they
+        // must not cause the device to be forgotten.
+        let (result, state) = self
+            .change(cause, move |device, events| {
+                let mut netlist = device.netlist()?;
+                match f(&mut netlist).and_then(|()| validate_netlist(events, netlist)) {
+                    Ok(netlist) => {
+                        device.set_netlist(netlist)?;
+                        Ok(Ok(()))
+                    }
+                    Err(error) => Ok(Err(error)),
+                }
+            })
+            .await?;
+        result?;
+        Ok(state.netlist)
+    }
+
+    /// Return the board to a state from the history, and respond with the resulting history entry.
+    ///
+    /// Undo and redo move through the history. Restoring an entry records it as a new change instead,
+    /// so the states in between are not lost.
+    async fn travel(&self, travel: Travel) -> Result<Entry> {
+        let history = Arc::clone(&self.history);
+        self.with_device(move |device, events| {
+            let before = BoardState::read(device)?;
+            history.lock().unwrap().observe(before);
+            let target = {
+                let history = history.lock().unwrap();
+                match travel {
+                    Travel::Undo => history
+                        .undo_target()
+                        .ok_or_else(|| Error::new(ErrorCode::Conflict, "Nothing to undo")),
+                    Travel::Redo => history
+                        .redo_target()
+                        .ok_or_else(|| Error::new(ErrorCode::Conflict, "Nothing to redo")),
+                    Travel::Restore(id) => history.get(id).ok_or_else(|| {
+                        Error::not_found(format!("No history entry with id {}", id))
+                    }),
+                }
+                .cloned()
+            };
+            let target = match target {
+                Ok(target) => target,
+                Err(error) => return Ok(Err(error)),
+            };
+            target.state.apply(device)?;
+            let state = events.sync(device)?;
+            let mut history = history.lock().unwrap();
+            match travel {
+                Travel::Restore(id) => history.record(&format!("restore #{}", id), state),
+                Travel::Undo | Travel::Redo => history.move_to(target.id),
+            }
+            let current = history.current().and_then(|id| history.get(id)).cloned();
+            Ok(Ok(
+                current.expect("history has a current entry after applying one")
+            ))
+        })
+        .await?
     }
 }
 
+/// Validate the given netlist, publishing an event on failure
+fn validate_netlist(events: &Events, netlist: Vec<Net>) -> Result<Vec<Net>> {
+    Ok(validate::netlist(netlist).inspect_err(|error| {
+        events.publish(Event::ValidationFailed {
+            error: error.to_string(),
+        });
+    })?)
+}
+
+#[derive(Serialize, ToSchema)]
+struct StatusResponse {
+    #[serde(flatten)]
+    status: Status,
+    /// Number of requests waiting for the device
+    queue_depth: usize,
+    /// Active lease, if any. While it is held, only its holder can make changes.
+    lease: Option<LeaseInfo>,
+}
+
+#[utoipa::path(
+    tag = "device",
+    responses((status = 200, body = StatusResponse))
+)]
 #[get("/status")]
 async fn get_status(shared: web::Data<Shared>) -> Result<impl Responder> {
     let status = shared
-        .device_manager
-        .lock()
-        .unwrap()
-        .status()
-        .map_err(Error)?;
-    Ok(web::Json(status))
+        .device
+        .run(|device_manager| device_manager.status())
+        .await??;
+    Ok(web::Json(StatusResponse {
+        status,
+        queue_depth: shared.device.queue_depth(),
+        lease: shared.leases.info(),
+    }))
 }
 
+#[utoipa::path(
+    tag = "nets",
+    responses((status = 200, body = [Net]), (status = 503, description = "Device not connected", body = Error))
+)]
 #[get("/nets")]
 async fn get_nets(shared: web::Data<Shared>) -> Result<impl Responder> {
-    Ok(web::Json(shared.netlist()?))
+    Ok(web::Json(shared.netlist().await?))
 }
 
+#[utoipa::path(
+    tag = "nets",
+    request_body = [Net],
+    responses((status = 200, body = bool), (status = 422, description = "Resulting netlist is invalid", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
 #[put("/nets")]
 async fn put_nets(shared: web::Data<Shared>, json: web::Json<Vec<Net>>) -> Result<impl Responder> {
+    let netlist = validate_netlist(&shared.events, json.into_inner())?;
+
     shared
-        .device_manager
-        .lock()
-        .unwrap()
-        .with_device(|device| device.set_netlist(validate::netlist(json.into_inner())?))
-        .map_err(Error)?;
+        .change("PUT /nets", move |device, _| device.set_netlist(netlist))
+        .await?;
 
     Ok(web::Json(true))
 }
 
+#[utoipa::path(
+    tag = "nets",
+    params(("index" = u8, Path, description = "Index of the net")),
+    responses((status = 200, body = Net), (status = 404, description = "No net with this index", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
 #[get("/nets/{index}")]
 async fn get_net(path: web::Path<u8>, shared: web::Data<Shared>) -> Result<impl Responder> {
     let index = path.into_inner();
-    Ok(web::Json(
-        shared.netlist()?.into_iter().find(|net| net.index == index),
-    ))
+    let net = shared
+        .netlist()
+        .await?
+        .into_iter()
+        .find(|net| net.index == index)
+        .ok_or_else(|| Error::not_found(format!("No net with index {}", index)))?;
+    Ok(web::Json(net))
+}
+
+/// Add a new net. Responds with the resulting netlist.
+#[utoipa::path(
+    tag = "nets",
+    request_body = NewNet,
+    responses((status = 200, body = [Net]), (status = 409, description = "No free net index left", body = Error), (status = 422, description = "Resulting netlist is invalid, or the net has no nodes", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[post("/nets")]
+async fn post_net(shared: web::Data<Shared>, json: web::Json<NewNet>) -> Result<impl Responder> {
+    let new_net = json.into_inner();
+    let netlist = shared
+        .update_netlist("POST /nets", move |netlist| {
+            let index = new_net
+                .add_to(netlist)
+                .ok_or_else(|| Error::new(ErrorCode::Conflict, "No free net index left"))?;
+            let net = netlist.iter().find(|net| net.index == index);
+            Ok(validate::has_nodes(net.expect("new net was added"))?)
+        })
+        .await?;
+    Ok(web::Json(netlist))
+}
+
+/// Update a single net (rename, recolor, add or remove nodes). Responds with the resulting netlist.
+#[utoipa::path(
+    tag = "nets",
+    params(("index" = u8, Path, description = "Index of the net")),
+    request_body = NetPatch,
+    responses((status = 200, body = [Net]), (status = 404, description = "No net with this index", body = Error), (status = 422, description = "Resulting netlist is invalid, or the net has no nodes", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[patch("/nets/{index}")]
+async fn patch_net(
+    path: web::Path<u8>,
+    shared: web::Data<Shared>,
+    json: web::Json<NetPatch>,
+) -> Result<impl Responder> {
+    let index = path.into_inner();
+    let patch = json.into_inner();
+    let netlist = shared
+        .update_netlist("PATCH /nets", move |netlist| {
+            let net = netlist
+                .iter_mut()
+                .find(|net| net.index == index)
+                .ok_or_else(|| Error::not_found(format!("No net with index {}", index)))?;
+            net.patch(patch);
+            Ok(validate::has_nodes(net)?)
+        })
+        .await?;
+    Ok(web::Json(netlist))
 }
 
+/// Remove a single net. Responds with the resulting netlist.
+#[utoipa::path(
+    tag = "nets",
+    params(("index" = u8, Path, description = "Index of the net")),
+    responses((status = 200, body = [Net]), (status = 404, description = "No net with this index", body = Error), (status = 422, description = "Resulting netlist is invalid", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[delete("/nets/{index}")]
+async fn delete_net(path: web::Path<u8>, shared: web::Data<Shared>) -> Result<impl Responder> {
+    let index = path.into_inner();
+    let netlist = shared
+        .update_netlist("DELETE /nets", move |netlist| {
+            let len = netlist.len();
+            netlist.retain(|net| net.index != index);
+            if netlist.len() == len {
+                return Err(Error::not_found(format!("No net with index {}", index)));
+            }
+            Ok(())
+        })
+        .await?;
+    Ok(web::Json(netlist))
+}
+
+#[utoipa::path(
+    tag = "device",
+    responses((status = 200, body = SupplySwitchPos), (status = 503, description = "Device not connected", body = Error))
+)]
 #[get("/supply_switch_pos")]
 async fn get_supply_switch_pos(shared: web::Data<Shared>) -> Result<impl Responder> {
     let pos = shared
-        .device_manager
-        .lock()
-        .unwrap()
-        .with_device(|device| device.supply_switch())
-        .map_err(Error)?;
+        .with_device(|device, _| device.supply_switch())
+        .await?;
     Ok(web::Json(pos.to_string()))
 }
 
+#[utoipa::path(
+    tag = "device",
+    params(("pos" = SupplySwitchPos, Path, description = "One of `8V`, `3.3V`, `5V`")),
+    responses((status = 200, body = SupplySwitchPos), (status = 400, body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
 #[put("/supply_switch_pos/{pos}")]
 async fn set_supply_switch_pos(
     path: web::Path<String>,
     shared: web::Data<Shared>,
 ) -> Result<impl Responder> {
-    let pos: SupplySwitchPos = path.into_inner().parse().map_err(Error)?;
+    let pos: SupplySwitchPos = path
+        .into_inner()
+        .parse()
+        .map_err(|_| Error::invalid_input("pos", "Expected one of: 8V, 3.3V, 5V"))?;
     shared
-        .device_manager
-        .lock()
-        .unwrap()
-        .with_device(|device| device.set_supply_switch(pos))
-        .map_err(Error)?;
+        .change("PUT /supply_switch_pos", move |device, _| {
+            device.set_supply_switch(pos)
+        })
+        .await?;
     Ok(web::Json(pos.to_string()))
 }
 
+#[utoipa::path(
+    tag = "device",
+    responses((status = 200, body = [ChipStatus]), (status = 503, description = "Device not connected", body = Error))
+)]
 #[get("/chip_status")]
 async fn get_chipstatus(shared: web::Data<Shared>) -> Result<impl Responder> {
-    let chipstatus = shared
-        .device_manager
-        .lock()
-        .unwrap()
-        .with_device(|device| device.chipstatus())
-        .map_err(Error)?;
+    let chipstatus = shared.with_device(|device, _| device.chipstatus()).await?;
     Ok(web::Json(chipstatus))
 }
 
-// #[get("/bridges")]
-// async fn bridges(shared: web::Data<Shared>) -> Result<impl Responder> {
-//     let nodefile: NodeFile = shared
-//         .device_manager
-//         .lock()
-//         .unwrap()
-//         .with_device(|device| device.netlist())
-//         .map_err(Error)?
-//         .into();
-
-//     Ok(web::Json(nodefile))
-// }
-
-// #[put("/bridges")]
-// async fn add_bridges(
-//     shared: web::Data<Shared>,
-//     json: web::Json<NodeFile>,
-// ) -> Result<impl Responder> {
-//     let nodefile = shared
-//         .device_manager
-//         .lock()
-//         .unwrap()
-//         .with_device(|device| {
-//             let mut nodefile: NodeFile = device.netlist()?.into();
-//             nodefile.add_from(json.0);
-//             device.send_nodefile(&nodefile)?;
-//             Ok(nodefile)
-//         })
-//         .map_err(Error)?;
-
-//     Ok(web::Json(nodefile))
-// }
-
-// #[delete("/bridges")]
-// async fn remove_bridges(
-//     shared: web::Data<Shared>,
-//     json: web::Json<NodeFile>,
-// ) -> Result<impl Responder> {
-//     let nodefile = shared
-//         .device_manager
-//         .lock()
-//         .unwrap()
-//         .with_device(|device| {
-//             let mut nodefile: NodeFile = device.netlist()?.into();
-//             nodefile.remove_from(json.0);
-//             device.send_nodefile(&nodefile)?;
-//             Ok(nodefile)
-//         })
-//         .map_err(Error)?;
-
-//     Ok(web::Json(nodefile))
-// }
+#[utoipa::path(
+    tag = "bridges",
+    responses((status = 200, body = [Bridge]), (status = 503, description = "Device not connected", body = Error))
+)]
+#[get("/bridges")]
+async fn get_bridges(shared: web::Data<Shared>) -> Result<impl Responder> {
+    Ok(web::Json(
+        shared.with_device(|device, _| device.bridgelist()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+struct GraphQuery {
+    /// `dot` (Graphviz, the default) or `graphml`
+    #[serde(default)]
+    format: GraphFormat,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum GraphFormat {
+    #[default]
+    Dot,
+    Graphml,
+}
+
+/// Export the nets and bridges as graph, like `jlctl net list -o dot`
+///
+/// Nodes are labeled by name, each bridge is an edge, and each net is a cluster in the net's color.
+#[utoipa::path(
+    tag = "bridges",
+    params(GraphQuery),
+    responses((status = 200, description = "Graph in Graphviz DOT or GraphML format", body = String, content_type = "text/vnd.graphviz"), (status = 400, body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[get("/graph")]
+async fn get_graph(
+    shared: web::Data<Shared>,
+    query: web::Query<GraphQuery>,
+) -> Result<impl Responder> {
+    let (netlist, bridgelist) = shared
+        .with_device(|device, _| Ok((device.netlist()?, device.bridgelist()?)))
+        .await?;
+    Ok(match query.format {
+        GraphFormat::Dot => HttpResponse::Ok()
+            .content_type("text/vnd.graphviz")
+            .body(graph::to_dot(&netlist, &bridgelist)),
+        GraphFormat::Graphml => HttpResponse::Ok()
+            .content_type("application/graphml+xml")
+            .body(graph::to_graphml(&netlist, &bridgelist)),
+    })
+}
+
+/// Replace the list of bridges
+///
+/// Accepts the same formats as `jlctl bridge set`: either JSON, or text (e.g. `GND-17,12-23`).
+#[utoipa::path(
+    tag = "bridges",
+    request_body(content = String, content_type = "text/plain", description = "Bridges, as text (e.g. `GND-17,12-23`) or JSON"),
+    responses((status = 200, body = [Bridge]), (status = 400, body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[put("/bridges")]
+async fn put_bridges(shared: web::Data<Shared>, body: String) -> Result<impl Responder> {
+    let bridgelist = parser::bridgelist_input(&body)
+        .map_err(|e| Error::invalid_input("bridges", e.to_string()))?;
+    let ((), state) = shared
+        .change("PUT /bridges", move |device, _| {
+            device.set_bridgelist(bridgelist)
+        })
+        .await?;
+    Ok(web::Json(state.bridgelist))
+}
+
+/// Add bridges to the current list of bridges, like `jlctl bridge add`
+#[utoipa::path(
+    tag = "bridges",
+    request_body(content = String, content_type = "text/plain", description = "Bridges, as text (e.g. `GND-17,12-23`) or JSON"),
+    responses((status = 200, body = [Bridge]), (status = 400, body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[post("/bridges")]
+async fn add_bridges(shared: web::Data<Shared>, body: String) -> Result<impl Responder> {
+    let bridgelist = parser::bridgelist_input(&body)
+        .map_err(|e| Error::invalid_input("bridges", e.to_string()))?;
+    let (bridgelist, _) = shared
+        .change("POST /bridges", move |device, _| {
+            device.add_bridges(bridgelist)
+        })
+        .await?;
+    Ok(web::Json(bridgelist))
+}
+
+/// Remove bridges from the current list of bridges, like `jlctl bridge remove`
+#[utoipa::path(
+    tag = "bridges",
+    request_body(content = String, content_type = "text/plain", description = "Bridges, as text (e.g. `GND-17,12-23`) or JSON"),
+    responses((status = 200, body = [Bridge]), (status = 400, body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[delete("/bridges")]
+async fn remove_bridges(shared: web::Data<Shared>, body: String) -> Result<impl Responder> {
+    let bridgelist = parser::bridgelist_input(&body)
+        .map_err(|e| Error::invalid_input("bridges", e.to_string()))?;
+    let (bridgelist, _) = shared
+        .change("DELETE /bridges", move |device, _| {
+            device.remove_bridges(bridgelist)
+        })
+        .await?;
+    Ok(web::Json(bridgelist))
+}
 
+#[utoipa::path(
+    tag = "bridges",
+    responses((status = 200, body = bool), (status = 503, description = "Device not connected", body = Error))
+)]
 #[post("/bridges/clear")]
 async fn clear_bridges(shared: web::Data<Shared>) -> Result<impl Responder> {
     shared
-        .device_manager
-        .lock()
-        .unwrap()
-        .with_device(|device| device.clear_nodefile())
-        .map_err(Error)?;
+        .change("POST /bridges/clear", move |device, _| {
+            device.set_bridgelist(vec![])
+        })
+        .await?;
 
     Ok(web::Json(true))
 }
 
-pub fn start(
-    device_manager: DeviceManager<FileLogger>,
-    listen_address: Option<&str>,
-) -> std::io::Result<String> {
-    let listener = TcpListener::bind(listen_address.unwrap_or("localhost:0"))?;
+/// Set color of the given light (node name, or a special name like 'glow', 'logo', ...)
+#[utoipa::path(
+    tag = "device",
+    params(("name" = String, Path, description = "Node name, or a special light like `glow` or `logo`")),
+    request_body = Color,
+    responses((status = 200, body = Color), (status = 503, description = "Device not connected", body = Error))
+)]
+#[put("/lights/{name}")]
+async fn put_light(
+    path: web::Path<String>,
+    shared: web::Data<Shared>,
+    json: web::Json<Color>,
+) -> Result<impl Responder> {
+    let name = path.into_inner();
+    let color = json.into_inner();
+    shared
+        .with_device(move |device, _| device.lightnet(name, color))
+        .await?;
+    Ok(web::Json(color))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RawInstruction {
+    instruction: String,
+    #[serde(default)]
+    args: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RawResponse {
+    /// Whether the device responded with `::ok`
+    success: bool,
+    /// Messages received before the response
+    #[schema(value_type = Vec<Object>)]
+    messages: Vec<Message>,
+}
+
+/// Send a raw instruction. Only available if the server was started with `--allow-raw`.
+#[utoipa::path(
+    tag = "device",
+    request_body = RawInstruction,
+    responses((status = 200, body = RawResponse), (status = 403, description = "Raw instructions are disabled", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[post("/raw")]
+async fn post_raw(
+    shared: web::Data<Shared>,
+    json: web::Json<RawInstruction>,
+) -> Result<impl Responder> {
+    if !shared.allow_raw {
+        return Err(Error::new(
+            ErrorCode::Forbidden,
+            "Raw instructions are disabled. Start the server with --allow-raw to enable them.",
+        ));
+    }
+    let RawInstruction { instruction, args } = json.into_inner();
+    let ((success, messages), _) = shared
+        .change("POST /raw", move |device, _| device.raw(instruction, args))
+        .await?;
+    Ok(web::Json(RawResponse { success, messages }))
+}
+
+#[utoipa::path(
+    tag = "device",
+    responses((status = 200, body = [FoundPort]))
+)]
+#[get("/ports")]
+async fn get_ports(shared: web::Data<Shared>) -> Result<impl Responder> {
+    let ports = shared
+        .device
+        .run(|device_manager| device_manager.list_ports())
+        .await??;
+    Ok(web::Json(ports))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SelectPort {
+    /// Port to use. If `None`, the port is detected dynamically.
+    port: Option<String>,
+}
+
+/// Switch to a different serial port. Responds with the new status.
+#[utoipa::path(
+    tag = "device",
+    request_body = SelectPort,
+    responses((status = 200, body = StatusResponse))
+)]
+#[put("/port")]
+async fn put_port(
+    shared: web::Data<Shared>,
+    json: web::Json<SelectPort>,
+) -> Result<impl Responder> {
+    let port = json.into_inner().port;
+    let status = shared
+        .device
+        .run(move |device_manager| {
+            device_manager.set_path(port);
+            device_manager.status()
+        })
+        .await??;
+    // make sure subscribers are notified about the switch, even if the new port works as well
+    shared.events.set_connected(false);
+    if status.connected {
+        shared.events.set_connected(true);
+    }
+    Ok(web::Json(StatusResponse {
+        status,
+        queue_depth: shared.device.queue_depth(),
+        lease: shared.leases.info(),
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+struct HistoryResponse {
+    /// ID of the entry matching the board's state, if any
+    current: Option<u64>,
+    /// Recorded states, oldest first
+    entries: Vec<Entry>,
+}
+
+/// List recorded board states
+#[utoipa::path(
+    tag = "history",
+    responses((status = 200, body = HistoryResponse))
+)]
+#[get("/history")]
+async fn get_history(shared: web::Data<Shared>) -> Result<impl Responder> {
+    let history = shared.history.lock().unwrap();
+    Ok(web::Json(HistoryResponse {
+        current: history.current(),
+        entries: history.entries().cloned().collect(),
+    }))
+}
+
+/// Return to the state before the last change. Responds with the entry that is now current.
+#[utoipa::path(
+    tag = "history",
+    responses((status = 200, body = Entry), (status = 409, description = "Nothing to undo", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[post("/undo")]
+async fn post_undo(shared: web::Data<Shared>) -> Result<impl Responder> {
+    Ok(web::Json(shared.travel(Travel::Undo).await?))
+}
+
+/// Reapply the last undone change. Responds with the entry that is now current.
+#[utoipa::path(
+    tag = "history",
+    responses((status = 200, body = Entry), (status = 409, description = "Nothing to redo", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[post("/redo")]
+async fn post_redo(shared: web::Data<Shared>) -> Result<impl Responder> {
+    Ok(web::Json(shared.travel(Travel::Redo).await?))
+}
+
+/// Apply the state recorded in the given entry. This is recorded as a new change, which can be undone.
+#[utoipa::path(
+    tag = "history",
+    params(("id" = u64, Path, description = "ID of the history entry")),
+    responses((status = 200, body = Entry), (status = 404, description = "No entry with this ID", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[post("/history/{id}/restore")]
+async fn post_restore(path: web::Path<u64>, shared: web::Data<Shared>) -> Result<impl Responder> {
+    Ok(web::Json(
+        shared.travel(Travel::Restore(path.into_inner())).await?,
+    ))
+}
+
+/// Take a snapshot of the board's current state, without storing it
+#[utoipa::path(
+    tag = "snapshots",
+    responses((status = 200, body = Snapshot), (status = 503, description = "Device not connected", body = Error))
+)]
+#[get("/state")]
+async fn get_state(shared: web::Data<Shared>) -> Result<impl Responder> {
+    Ok(web::Json(
+        shared
+            .with_device(|device, _| Snapshot::capture(device))
+            .await?,
+    ))
+}
+
+/// List stored snapshots
+#[utoipa::path(
+    tag = "snapshots",
+    responses((status = 200, body = [crate::snapshot::Summary]))
+)]
+#[get("/snapshots")]
+async fn get_snapshots(shared: web::Data<Shared>) -> Result<impl Responder> {
+    Ok(web::Json(shared.snapshots.list()?))
+}
+
+#[utoipa::path(
+    tag = "snapshots",
+    params(("name" = String, Path, description = "Name of the snapshot")),
+    responses((status = 200, body = Snapshot), (status = 404, description = "No snapshot with this name", body = Error))
+)]
+#[get("/snapshots/{name}")]
+async fn get_snapshot(
+    path: web::Path<String>,
+    shared: web::Data<Shared>,
+) -> Result<impl Responder> {
+    Ok(web::Json(shared.snapshots.load(&path)?))
+}
+
+/// Take a snapshot of the board's current state, and store it under the given name (replacing an existing one)
+#[utoipa::path(
+    tag = "snapshots",
+    params(("name" = String, Path, description = "Name of the snapshot")),
+    responses((status = 200, body = Snapshot), (status = 400, description = "Invalid name", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[put("/snapshots/{name}")]
+async fn put_snapshot(
+    path: web::Path<String>,
+    shared: web::Data<Shared>,
+) -> Result<impl Responder> {
+    Store::check_name(&path).map_err(|e| Error::invalid_input("name", e.to_string()))?;
+    let snapshot = shared
+        .with_device(|device, _| Snapshot::capture(device))
+        .await?;
+    shared.snapshots.save(&path, &snapshot)?;
+    Ok(web::Json(snapshot))
+}
+
+#[derive(Serialize, ToSchema)]
+struct RestoreResponse {
+    /// Differences between the snapshot and the state read back from the board. Empty if everything was restored.
+    /// Lights are not included, since they cannot be read back.
+    mismatches: Vec<Change>,
+}
+
+/// Apply a stored snapshot to the board, and verify it by reading the state back
+#[utoipa::path(
+    tag = "snapshots",
+    params(("name" = String, Path, description = "Name of the snapshot")),
+    responses((status = 200, body = RestoreResponse), (status = 404, description = "No snapshot with this name", body = Error), (status = 503, description = "Device not connected", body = Error))
+)]
+#[post("/snapshots/{name}/restore")]
+async fn post_snapshot_restore(
+    path: web::Path<String>,
+    shared: web::Data<Shared>,
+) -> Result<impl Responder> {
+    let snapshot = shared.snapshots.load(&path)?;
+    let (mismatches, _) = shared
+        .change("POST /snapshots/{name}/restore", move |device, _| {
+            snapshot.restore(device)
+        })
+        .await?;
+    Ok(web::Json(RestoreResponse { mismatches }))
+}
+
+/// Show the active lease, if any
+#[utoipa::path(
+    tag = "lease",
+    responses((status = 200, body = Option<LeaseInfo>))
+)]
+#[get("/lease")]
+async fn get_lease(shared: web::Data<Shared>) -> impl Responder {
+    web::Json(shared.leases.info())
+}
+
+/// Acquire exclusive write access to the board, for the given duration.
+///
+/// To renew a lease, send this request again with its token in the `X-Lease-Token` header.
+#[utoipa::path(
+    tag = "lease",
+    request_body = LeaseRequest,
+    responses((status = 200, body = lease::Granted), (status = 400, description = "Invalid holder or duration", body = Error), (status = 423, description = "Someone else holds the lease", body = Error))
+)]
+#[post("/lease")]
+async fn post_lease(
+    req: HttpRequest,
+    shared: web::Data<Shared>,
+    json: web::Json<LeaseRequest>,
+) -> Result<impl Responder> {
+    let request = json.into_inner();
+    let duration = Duration::from_secs(request.duration);
+    let granted = shared.leases.acquire(req.headers(), request)?;
+    shared.events.publish(Event::LeaseAcquired {
+        holder: granted.lease.holder.clone(),
+        expires_at: granted.lease.expires_at.clone(),
+    });
+
+    // announce the expiry, unless the lease was renewed or released in the meantime
+    let leases = Arc::clone(&shared.leases);
+    let events = Arc::clone(&shared.events);
+    let token = granted.token.clone();
+    actix_web::rt::spawn(async move {
+        actix_web::rt::time::sleep(duration).await;
+        if let Some(lease) = leases.expire(&token) {
+            events.publish(Event::LeaseExpired {
+                holder: lease.holder,
+            });
+        }
+    });
+
+    Ok(web::Json(granted))
+}
+
+/// Release the lease. Requires its token in the `X-Lease-Token` header.
+#[utoipa::path(
+    tag = "lease",
+    responses((status = 200, body = LeaseInfo), (status = 404, description = "No lease is active", body = Error), (status = 423, description = "Someone else holds the lease", body = Error))
+)]
+#[delete("/lease")]
+async fn delete_lease(shared: web::Data<Shared>) -> Result<impl Responder> {
+    let lease = shared
+        .leases
+        .release()
+        .ok_or_else(|| Error::not_found("No lease is active"))?;
+    shared.events.publish(Event::LeaseReleased {
+        holder: lease.holder.clone(),
+    });
+    Ok(web::Json(lease))
+}
+
+#[utoipa::path(
+    tag = "device",
+    responses((status = 200, description = "Metrics in Prometheus text format", body = String, content_type = "text/plain"))
+)]
+#[get("/metrics")]
+async fn get_metrics(shared: web::Data<Shared>) -> impl Responder {
+    let mut body = METRICS.render();
+    body += "# HELP jlctl_queue_depth Requests waiting for the device\n";
+    body += "# TYPE jlctl_queue_depth gauge\n";
+    body += &format!("jlctl_queue_depth {}\n", shared.device.queue_depth());
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+/// A socket the server listens on
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+/// Start the server, and block until it stops.
+///
+/// The listen address is either `<host>:<port>`, or `unix:<path>` to listen on a Unix domain socket.
+pub fn start(device_manager: DeviceManager<FileLogger>, config: Config) -> std::io::Result<String> {
+    let address = config
+        .listen_address
+        .clone()
+        .unwrap_or_else(|| "localhost:0".to_string());
+
+    #[cfg(unix)]
+    if let Some(path) = address.strip_prefix("unix:") {
+        let path = PathBuf::from(path);
+        let listener = bind_unix(&path, config.socket_mode)?;
+        let result = start_with_listener(
+            device_manager,
+            Listener::Unix(listener, path.clone()),
+            config,
+        );
+        _ = std::fs::remove_file(&path);
+        result?;
+        return Ok(address);
+    }
+
+    let listener = TcpListener::bind(&address)?;
     let address = listener.local_addr()?.to_string();
-    start_with_listener(device_manager, listener)?;
+    start_with_listener(device_manager, Listener::Tcp(listener), config)?;
     Ok(address)
 }
 
+/// Bind a Unix domain socket at the given path, and restrict access to it with the given mode.
+///
+/// A stale socket file (left behind by a server that did not shut down cleanly) is removed.
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path, mode: u32) -> std::io::Result<UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("Another server is already listening on {:?}", path),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
 #[actix_web::main]
 async fn start_with_listener(
     device_manager: DeviceManager<FileLogger>,
-    listener: TcpListener,
+    listener: Listener,
+    config: Config,
 ) -> std::io::Result<()> {
-    let device_manager = Arc::new(Mutex::new(device_manager));
-
-    let address = listener.local_addr()?;
-    let ip = address.ip();
-    let listen_address = format!(
-        "{}:{}",
-        if ip.is_loopback() {
-            "localhost".to_string()
-        } else {
-            ip.to_string()
-        },
-        address.port()
-    );
-    info!("Starting HTTP server, listening on {}", listen_address);
+    let shared = web::Data::new(Shared {
+        device: DeviceActor::spawn(device_manager, config.request_timeout),
+        events: Arc::new(Events::new()),
+        history: Arc::new(Mutex::new(History::new(config.history_size))),
+        leases: Arc::new(Leases::new(config.max_lease)),
+        snapshots: Store::new(config.snapshot_dir),
+        origins: Arc::new(Origins::new(config.allowed_origins)),
+        allow_raw: config.allow_raw,
+    });
 
-    HttpServer::new(move || {
+    if let Some(interval) = config.poll_interval {
+        let device = shared.device.clone();
+        let events = Arc::clone(&shared.events);
+        std::thread::spawn(move || events::poll(device, events, interval));
+    }
+
+    if let Some(dir) = &config.ui_dir {
+        jumperlab::check_dir(dir)?;
+        let dir = dir.clone();
+        let events = Arc::clone(&shared.events);
+        std::thread::spawn(move || jumperlab::watch(dir, events));
+    }
+    let assets = jumperlab::Assets::new(config.ui_dir).map(web::Data::new);
+
+    #[cfg(feature = "tls")]
+    let tls_config = match &config.tls {
+        Some(tls) => Some(tls.server_config().map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{:#}", e))
+        })?),
+        None => None,
+    };
+    #[cfg(feature = "tls")]
+    let scheme = if tls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    #[cfg(not(feature = "tls"))]
+    let scheme = "http";
+
+    match &listener {
+        Listener::Tcp(listener) => {
+            let address = listener.local_addr()?;
+            let ip = address.ip();
+            let listen_address = format!(
+                "{}:{}",
+                if ip.is_loopback() {
+                    "localhost".to_string()
+                } else {
+                    ip.to_string()
+                },
+                address.port()
+            );
+            info!(
+                "Starting HTTP server, listening on {}://{}",
+                scheme, listen_address
+            );
+            if !ip.is_loopback() && !config.auth.is_enabled() {
+                warn!("Listening on a public address, without authentication. Anyone who can reach {} can control the board.", listen_address);
+            }
+            if assets.is_some() {
+                println!(
+                    "\n    To open Jumperlab, visit: {}://{}/jumperlab\n",
+                    scheme, listen_address
+                );
+            }
+        }
+        #[cfg(unix)]
+        Listener::Unix(_, path) => {
+            #[cfg(feature = "tls")]
+            if tls_config.is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "TLS is not supported for Unix domain sockets",
+                ));
+            }
+            info!("Starting HTTP server, listening on unix:{}", path.display());
+        }
+    }
+
+    let auth = Arc::new(config.auth);
+    let origins = Arc::clone(&shared.origins);
+
+    let server = HttpServer::new(move || {
+        let cors_origins = Arc::clone(&origins);
         let cors = Cors::default()
-            .allowed_origin_fn(|origin, _req_head| {
-                origin.as_bytes().starts_with(b"http://localhost:")
+            .allowed_origin_fn(move |origin, _req_head| {
+                origin
+                    .to_str()
+                    .is_ok_and(|origin| cors_origins.allows(origin))
             })
-            .allowed_methods(vec!["GET", "PUT", "POST", "DELETE"])
+            .allowed_methods(vec!["GET", "PUT", "POST", "PATCH", "DELETE"])
             .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
             .allowed_header(http::header::CONTENT_TYPE)
-            .max_age(3600);
+            .allowed_header(lease::LEASE_HEADER)
+            .max_age(3600)
+            // CORS only decides which pages may read responses. Writes are checked by `Origins::check`,
+            // which also accepts same-origin requests.
+            .block_on_origin_mismatch(false);
 
+        let auth = Arc::clone(&auth);
+        let origins = Arc::clone(&origins);
+        let leases = Arc::clone(&shared.leases);
         let app = App::new()
-            .wrap(Logger::default())
+            // registered before auth, so it runs after it: unauthenticated clients learn nothing about the lease
+            .wrap_fn(move |req, srv| {
+                let result = match leases.check(&req) {
+                    Ok(()) => Ok(srv.call(req)),
+                    Err(error) => Err(req.into_response(error.error_response())),
+                };
+                async move {
+                    match result {
+                        Ok(response) => response.await,
+                        Err(response) => Ok(response),
+                    }
+                }
+            })
+            .wrap_fn(move |req, srv| {
+                let origin = if Role::required_for(req.method()) == Role::ReadWrite {
+                    origins.check(req.request())
+                } else {
+                    Ok(())
+                };
+                let result = match origin.and_then(|()| auth.check(&req)) {
+                    Ok(()) => Ok(srv.call(req)),
+                    Err(error) => {
+                        let mut response = error.error_response();
+                        if error.code == ErrorCode::Unauthorized {
+                            response.headers_mut().insert(
+                                http::header::WWW_AUTHENTICATE,
+                                http::header::HeaderValue::from_static(auth.challenge()),
+                            );
+                        }
+                        Err(req.into_response(response))
+                    }
+                };
+                async move {
+                    match result {
+                        Ok(response) => response.await,
+                        Err(response) => Ok(response),
+                    }
+                }
+            })
+            .wrap_fn(|req, srv| {
+                let method = req.method().to_string();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await?;
+                    // label by pattern (e.g. `/nets/{index}`), to keep the number of series bounded
+                    let route = response.request().match_pattern();
+                    METRICS.http_request(
+                        route.as_deref().unwrap_or("unmatched"),
+                        &method,
+                        response.status().as_u16(),
+                    );
+                    Ok(response)
+                }
+            })
+            .wrap(auth::access_logger())
             .wrap(cors)
             .wrap(NormalizePath::trim())
-            .app_data(web::Data::new(Shared {
-                device_manager: Arc::clone(&device_manager),
-            }))
+            .app_data(shared.clone())
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _req| error::extractor_error("body", err)),
+            )
+            .app_data(
+                web::PathConfig::default()
+                    .error_handler(|err, _req| error::extractor_error("path", err)),
+            )
+            .app_data(
+                web::QueryConfig::default()
+                    .error_handler(|err, _req| error::extractor_error("query", err)),
+            )
             .service(get_status)
             .service(get_net)
             .service(get_nets)
             .service(put_nets)
+            .service(post_net)
+            .service(patch_net)
+            .service(delete_net)
             .service(set_supply_switch_pos)
             .service(get_supply_switch_pos)
+            .service(get_bridges)
+            .service(put_bridges)
+            .service(add_bridges)
+            .service(remove_bridges)
             .service(clear_bridges)
-            .service(get_chipstatus);
+            .service(get_graph)
+            .service(get_chipstatus)
+            .service(put_light)
+            .service(post_raw)
+            .service(get_ports)
+            .service(put_port)
+            .service(get_metrics)
+            .service(get_history)
+            .service(post_undo)
+            .service(post_redo)
+            .service(post_restore)
+            .service(get_state)
+            .service(get_snapshots)
+            .service(get_snapshot)
+            .service(put_snapshot)
+            .service(post_snapshot_restore)
+            .service(get_lease)
+            .service(post_lease)
+            .service(delete_lease)
+            .service(events::get_events)
+            .service(openapi::get_openapi);
 
-        #[cfg(feature = "jumperlab")]
-        {
-            println!(
-                "\n    To open Jumperlab, visit: http://{}/jumperlab\n",
-                listen_address
-            );
-            return jumperlab::add_to_app(app);
+        match &assets {
+            Some(assets) => jumperlab::add_to_app(app, assets.clone()),
+            None => app,
         }
+    });
 
-        #[allow(unreachable_code, clippy::let_and_return)]
-        app
-    })
-    .workers(1)
-    .listen(listener)?
-    .run()
-    .await
+    let server = match listener {
+        #[cfg(feature = "tls")]
+        Listener::Tcp(listener) => match tls_config {
+            Some(tls_config) => server.listen_rustls_0_21(listener, tls_config)?,
+            None => server.listen(listener)?,
+        },
+        #[cfg(not(feature = "tls"))]
+        Listener::Tcp(listener) => server.listen(listener)?,
+        #[cfg(unix)]
+        Listener::Unix(listener, _) => server.listen_uds(listener)?,
+    };
+
+    server.run().await
 }
@@ -0,0 +1,152 @@
+use crate::{device_manager::DeviceManager, logger::FileLogger, metrics::METRICS};
+use log::debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use super::error::{Error, ErrorCode};
+
+type Job = Box<dyn FnOnce(&mut DeviceManager<FileLogger>) + Send>;
+
+/// Handle to the device actor.
+///
+/// The actor is a dedicated thread, which owns the [`DeviceManager`] and processes jobs one at a time.
+/// This serializes access to the board, without blocking the HTTP workers while waiting for it.
+#[derive(Clone)]
+pub struct DeviceActor {
+    sender: Sender<Job>,
+    queue_depth: Arc<AtomicUsize>,
+    timeout: Duration,
+}
+
+impl DeviceActor {
+    /// Spawn the actor thread.
+    ///
+    /// Requests that are not started within `timeout` fail with [`ErrorCode::DeviceTimeout`].
+    pub fn spawn(mut device_manager: DeviceManager<FileLogger>, timeout: Duration) -> Self {
+        let (sender, receiver) = channel::<Job>();
+        std::thread::Builder::new()
+            .name("device".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    job(&mut device_manager);
+                }
+                debug!("Device actor stopped");
+            })
+            .expect("spawn device actor");
+        Self {
+            sender,
+            queue_depth: Arc::new(AtomicUsize::new(0)),
+            timeout,
+        }
+    }
+
+    /// Number of jobs that are waiting to be processed
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth.load(Ordering::SeqCst)
+    }
+
+    /// Run the given closure on the actor thread, and wait for the result.
+    ///
+    /// The timeout only applies while the job is waiting in the queue: if it times out, or the returned future
+    /// is dropped (e.g. because the client went away) before the job was started, the job is skipped.
+    /// Once started, the job runs to completion, so a failed request never changes the board.
+    pub async fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DeviceManager<FileLogger>) -> T + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let (start, mut started) = oneshot::channel();
+        self.enqueue(f, start, reply)?;
+        match tokio::time::timeout(self.timeout, &mut started).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => return Err(stopped()),
+            Err(_) => {
+                // the job may have started just now; closing makes sure it is either seen here, or skipped
+                started.close();
+                if started.try_recv().is_err() {
+                    METRICS.request_timeout();
+                    return Err(Error::new(
+                        ErrorCode::DeviceTimeout,
+                        format!("Request was not started within {:?}", self.timeout),
+                    ));
+                }
+            }
+        }
+        result.await.map_err(|_| stopped())
+    }
+
+    /// Like [`DeviceActor::run`], but blocks the current thread. Must not be called from async code.
+    pub fn run_blocking<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DeviceManager<FileLogger>) -> T + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let (start, _started) = oneshot::channel();
+        self.enqueue(f, start, reply)?;
+        result.blocking_recv().map_err(|_| stopped())
+    }
+
+    fn enqueue<T, F>(
+        &self,
+        f: F,
+        start: oneshot::Sender<()>,
+        reply: oneshot::Sender<T>,
+    ) -> Result<(), Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DeviceManager<FileLogger>) -> T + Send + 'static,
+    {
+        let queue_depth = Arc::clone(&self.queue_depth);
+        queue_depth.fetch_add(1, Ordering::SeqCst);
+        let job: Job = Box::new(move |device_manager| {
+            queue_depth.fetch_sub(1, Ordering::SeqCst);
+            if start.send(()).is_err() {
+                debug!("Skipping cancelled job");
+                return;
+            }
+            _ = reply.send(f(device_manager));
+        });
+        self.sender.send(job).map_err(|_| {
+            self.queue_depth.fetch_sub(1, Ordering::SeqCst);
+            stopped()
+        })
+    }
+}
+
+fn stopped() -> Error {
+    Error::new(ErrorCode::DeviceError, "Device actor stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    #[actix_web::test]
+    async fn test_timeout_only_applies_to_queued_jobs() {
+        let log = std::env::temp_dir().join(format!("jlctl-actor-test-{}.log", std::process::id()));
+        let device_manager = DeviceManager::new(None, FileLogger::new(&log).unwrap());
+        let actor = DeviceActor::spawn(device_manager, Duration::from_millis(100));
+
+        let slow = actor.run(|_| std::thread::sleep(Duration::from_millis(300)));
+        let ran = Arc::new(AtomicBool::new(false));
+        let queued = actor.run({
+            let ran = Arc::clone(&ran);
+            move |_| ran.store(true, Ordering::SeqCst)
+        });
+        let (slow, queued) = tokio::join!(slow, queued);
+
+        // the slow job was started in time, so it completes, even though it took longer than the timeout
+        assert!(slow.is_ok());
+        // the other job was still waiting, so it fails and is never run
+        assert_eq!(queued.unwrap_err().code, ErrorCode::DeviceTimeout);
+        actor.run(|_| ()).await.unwrap();
+        assert!(!ran.load(Ordering::SeqCst));
+        _ = std::fs::remove_file(log);
+    }
+}
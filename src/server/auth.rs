@@ -0,0 +1,348 @@
+use actix_web::{
+    dev::ServiceRequest,
+    http::{header, Method},
+    middleware::Logger,
+    HttpRequest,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use super::error::{Error, ErrorCode};
+
+/// What a client is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Only requests that do not modify anything (`GET`, `HEAD`, `OPTIONS`)
+    ReadOnly,
+    /// All requests
+    ReadWrite,
+}
+
+impl Role {
+    /// Role needed to perform a request with the given method
+    pub(super) fn required_for(method: &Method) -> Self {
+        if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            Role::ReadOnly
+        } else {
+            Role::ReadWrite
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Credential {
+    /// Sent as `Authorization: Bearer <token>`, or as `access_token` query parameter
+    Token(String),
+    /// Sent as `Authorization: Basic <base64(user:password)>`
+    Basic { user: String, password: String },
+}
+
+/// Credentials accepted by the server, each with the role it grants.
+///
+/// If no credentials are configured, authentication is disabled.
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    credentials: Vec<(Credential, Role)>,
+}
+
+impl Auth {
+    pub fn is_enabled(&self) -> bool {
+        !self.credentials.is_empty()
+    }
+
+    /// Accept the given bearer token
+    pub fn add_token(&mut self, token: String, role: Role) {
+        self.credentials.push((Credential::Token(token), role));
+    }
+
+    /// Accept the given user, for basic auth. Expects `user:password`.
+    pub fn add_user(&mut self, user_password: &str, role: Role) -> anyhow::Result<()> {
+        let (user, password) = user_password
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Expected <user>:<password>"))?;
+        self.credentials.push((
+            Credential::Basic {
+                user: user.to_string(),
+                password: password.to_string(),
+            },
+            role,
+        ));
+        Ok(())
+    }
+
+    /// Check if the given request is allowed
+    pub fn check(&self, req: &ServiceRequest) -> Result<(), Error> {
+        if !self.is_enabled() || req.method() == Method::OPTIONS {
+            // preflight requests never carry credentials
+            return Ok(());
+        }
+        let authorization = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        let role = match authorization {
+            Some(authorization) => self.role_for_header(authorization),
+            // browsers cannot set headers for WebSocket connections
+            None => query_token(req.query_string()).and_then(|token| self.role_for_token(&token)),
+        };
+        match role {
+            Some(role) if role >= Role::required_for(req.method()) => Ok(()),
+            Some(_) => Err(Error::new(
+                ErrorCode::Forbidden,
+                "These credentials only allow read access",
+            )),
+            None => Err(Error::new(
+                ErrorCode::Unauthorized,
+                "Missing or invalid credentials",
+            )),
+        }
+    }
+
+    /// Value for the `WWW-Authenticate` header, sent along with 401 responses
+    pub fn challenge(&self) -> &'static str {
+        let basic = self
+            .credentials
+            .iter()
+            .any(|(credential, _)| matches!(credential, Credential::Basic { .. }));
+        if basic {
+            "Basic realm=\"jlctl\""
+        } else {
+            "Bearer realm=\"jlctl\""
+        }
+    }
+
+    fn role_for_header(&self, authorization: &str) -> Option<Role> {
+        let (scheme, value) = authorization.split_once(' ')?;
+        if scheme.eq_ignore_ascii_case("bearer") {
+            self.role_for_token(value.trim())
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(BASE64.decode(value.trim()).ok()?).ok()?;
+            let (user, password) = decoded.split_once(':')?;
+            self.credentials
+                .iter()
+                .find(|(credential, _)| match credential {
+                    Credential::Basic {
+                        user: u,
+                        password: p,
+                    } => constant_time_eq(u, user) & constant_time_eq(p, password),
+                    _ => false,
+                })
+                .map(|(_, role)| *role)
+        } else {
+            None
+        }
+    }
+
+    fn role_for_token(&self, token: &str) -> Option<Role> {
+        self.credentials
+            .iter()
+            .find(|(credential, _)| match credential {
+                Credential::Token(t) => constant_time_eq(t, token),
+                _ => false,
+            })
+            .map(|(_, role)| *role)
+    }
+}
+
+/// Origins allowed to make cross-origin requests (`--allow-origin`).
+///
+/// If none are configured, any `http://localhost:<port>` origin is allowed.
+#[derive(Debug, Clone, Default)]
+pub struct Origins(Vec<String>);
+
+impl Origins {
+    pub fn new(allowed: Vec<String>) -> Self {
+        Self(allowed)
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        if self.0.is_empty() {
+            origin.starts_with("http://localhost:")
+        } else {
+            self.0
+                .iter()
+                .any(|allowed| allowed == "*" || allowed == origin)
+        }
+    }
+
+    /// Check the `Origin` of a request made by a web page.
+    ///
+    /// CORS only keeps pages on other origins from reading responses. Requests that need no preflight
+    /// (e.g. a form posting `text/plain`) still reach the handler, so writes must be rejected here.
+    /// Requests without `Origin` (not sent by a browser) and same-origin requests (e.g. from the
+    /// jumperlab served by this server) are accepted.
+    pub fn check(&self, req: &HttpRequest) -> Result<(), Error> {
+        let Some(origin) = req.headers().get(header::ORIGIN) else {
+            return Ok(());
+        };
+        let origin = origin.to_str().unwrap_or_default();
+        let info = req.connection_info();
+        if self.allows(origin) || origin == format!("{}://{}", info.scheme(), info.host()) {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorCode::Forbidden,
+                format!("Requests from origin {:?} are not allowed", origin),
+            ))
+        }
+    }
+}
+
+/// Extract `access_token` from a query string
+fn query_token(query: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == "access_token").then(|| value.to_string())
+    })
+}
+
+/// Access logger, like actix' default `Logger`, but with `access_token` redacted from the
+/// request line and the referer
+pub(super) fn access_logger() -> Logger {
+    Logger::new(r#"%a "%{request_line}xi" %s %b "%{referer}xi" "%{User-Agent}i" %T"#)
+        .custom_request_replace("request_line", request_line)
+        .custom_request_replace("referer", |req| {
+            req.headers()
+                .get(header::REFERER)
+                .and_then(|referer| referer.to_str().ok())
+                .map(redact_token)
+                .unwrap_or_else(|| "-".to_string())
+        })
+}
+
+/// Request line as logged by `%r`, with `access_token` redacted
+fn request_line(req: &ServiceRequest) -> String {
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    format!(
+        "{} {} {:?}",
+        req.method(),
+        redact_token(path_and_query),
+        req.version()
+    )
+}
+
+/// Replace the value of the `access_token` query parameter in the given URL
+fn redact_token(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let query: Vec<&str> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("access_token", _)) => "access_token=REDACTED",
+            _ => pair,
+        })
+        .collect();
+    format!("{}?{}", base, query.join("&"))
+}
+
+/// Compare strings without exiting early, to not leak the position of the first difference through timing
+pub(super) fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn auth() -> Auth {
+        let mut auth = Auth::default();
+        auth.add_token("secret".to_string(), Role::ReadWrite);
+        auth.add_token("peek".to_string(), Role::ReadOnly);
+        auth.add_user("lab:bench", Role::ReadOnly).unwrap();
+        auth
+    }
+
+    fn check(method: Method, authorization: Option<&str>, uri: &str) -> Option<ErrorCode> {
+        let mut req = TestRequest::default().method(method).uri(uri);
+        if let Some(authorization) = authorization {
+            req = req.insert_header((header::AUTHORIZATION, authorization));
+        }
+        auth().check(&req.to_srv_request()).err().map(|e| e.code)
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(
+            check(Method::GET, None, "/nets"),
+            Some(ErrorCode::Unauthorized)
+        );
+        assert_eq!(check(Method::OPTIONS, None, "/nets"), None);
+        assert_eq!(check(Method::PUT, Some("Bearer secret"), "/nets"), None);
+        assert_eq!(check(Method::GET, Some("Bearer peek"), "/nets"), None);
+        assert_eq!(
+            check(Method::PUT, Some("Bearer peek"), "/nets"),
+            Some(ErrorCode::Forbidden)
+        );
+        assert_eq!(
+            check(Method::GET, Some("Bearer wrong"), "/nets"),
+            Some(ErrorCode::Unauthorized)
+        );
+        // "lab:bench"
+        assert_eq!(
+            check(Method::GET, Some("Basic bGFiOmJlbmNo"), "/nets"),
+            None
+        );
+        assert_eq!(check(Method::GET, None, "/events?access_token=peek"), None);
+    }
+
+    fn check_origin(method: Method, origin: Option<&str>) -> Option<ErrorCode> {
+        let mut req = TestRequest::default()
+            .method(method)
+            .uri("/bridges")
+            .insert_header((header::HOST, "192.168.1.5:8080"));
+        if let Some(origin) = origin {
+            req = req.insert_header((header::ORIGIN, origin));
+        }
+        let origins = Origins::new(vec!["http://lab.example".to_string()]);
+        origins.check(&req.to_http_request()).err().map(|e| e.code)
+    }
+
+    #[test]
+    fn test_check_origin() {
+        assert_eq!(check_origin(Method::POST, None), None);
+        assert_eq!(check_origin(Method::POST, Some("http://lab.example")), None);
+        assert_eq!(
+            check_origin(Method::POST, Some("http://192.168.1.5:8080")),
+            None
+        );
+        assert_eq!(
+            check_origin(Method::POST, Some("http://evil.example")),
+            Some(ErrorCode::Forbidden)
+        );
+        assert_eq!(
+            check_origin(Method::POST, Some("http://localhost:3000")),
+            Some(ErrorCode::Forbidden)
+        );
+        assert!(Origins::default().allows("http://localhost:3000"));
+        assert!(!Origins::default().allows("http://localhost.evil.example"));
+        assert!(Origins::new(vec!["*".to_string()]).allows("http://evil.example"));
+    }
+
+    #[test]
+    fn test_request_line_redacts_token() {
+        let req = TestRequest::default()
+            .uri("/events?since=3&access_token=secret")
+            .to_srv_request();
+        assert_eq!(
+            request_line(&req),
+            "GET /events?since=3&access_token=REDACTED HTTP/1.1"
+        );
+        let req = TestRequest::default().uri("/nets").to_srv_request();
+        assert_eq!(request_line(&req), "GET /nets HTTP/1.1");
+        assert_eq!(
+            redact_token("http://localhost:8080/?access_token=secret"),
+            "http://localhost:8080/?access_token=REDACTED"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{device, snapshot, validate};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Stable error codes, included in every error response.
+///
+/// Clients should use these to react to specific errors, instead of matching on the message.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request could not be parsed (400)
+    InvalidInput,
+    /// Credentials are missing or invalid (401)
+    Unauthorized,
+    /// The requested resource does not exist (404)
+    NotFound,
+    /// The request is not allowed by the server configuration, or the given credentials (403)
+    Forbidden,
+    /// The request conflicts with the current state, e.g. there is nothing to undo (409)
+    Conflict,
+    /// Someone else holds the lease on the board (423)
+    Locked,
+    /// The request was well-formed, but the resulting netlist is invalid (422)
+    ValidationFailed,
+    /// No device is connected, or the connection was lost (503)
+    DeviceNotConnected,
+    /// The device did not respond in time, or the request waited too long for the device and was skipped (504)
+    DeviceTimeout,
+    /// The device responded with an error, or something unexpected (502)
+    DeviceError,
+}
+
+impl ErrorCode {
+    fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidInput => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::Locked => StatusCode::LOCKED,
+            ErrorCode::ValidationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::DeviceNotConnected => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::DeviceTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::DeviceError => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+/// Field-level detail about invalid input
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Detail {
+    /// Index of the affected net, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<u8>,
+    pub field: String,
+    pub message: String,
+}
+
+/// Error returned by request handlers.
+///
+/// Serialized as `{ "error": "<message>", "code": "<code>", "details": [...] }`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Error {
+    #[serde(rename = "error")]
+    pub message: String,
+    pub code: ErrorCode,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<Detail>,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            code,
+            details: vec![],
+        }
+    }
+
+    /// Invalid input, for the given field
+    pub fn invalid_input(field: &str, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self {
+            details: vec![Detail {
+                index: None,
+                field: field.to_string(),
+                message: message.clone(),
+            }],
+            ..Self::new(ErrorCode::InvalidInput, message)
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<validate::Error> for Error {
+    fn from(error: validate::Error) -> Self {
+        Self {
+            message: error.to_string(),
+            code: ErrorCode::ValidationFailed,
+            details: error
+                .0
+                .into_iter()
+                .map(|problem| Detail {
+                    index: Some(problem.index),
+                    field: problem.field.to_string(),
+                    message: problem.message,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<validate::Error>() {
+            Ok(error) => return error.into(),
+            Err(error) => error,
+        };
+        match error.downcast_ref::<snapshot::Error>() {
+            Some(snapshot::Error::InvalidName(_)) => {
+                return Self::invalid_input("name", error.to_string())
+            }
+            Some(snapshot::Error::NotFound(_)) => return Self::not_found(error.to_string()),
+            Some(snapshot::Error::UnsupportedVersion(_)) => {
+                return Self::new(ErrorCode::Conflict, format!("{:#}", error))
+            }
+            None => {}
+        }
+        let code = match error.downcast_ref::<device::Error>() {
+            Some(device::Error::NotConnected(_)) => ErrorCode::DeviceNotConnected,
+            Some(device::Error::Timeout) => ErrorCode::DeviceTimeout,
+            Some(device::Error::Protocol(_)) | None => ErrorCode::DeviceError,
+        };
+        Self::new(code, format!("{:#}", error))
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        self.code.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self)
+    }
+}
+
+/// Turn errors from actix extractors (JSON body, path segments, ...) into an [`Error`]
+pub fn extractor_error<E: std::fmt::Display>(field: &str, error: E) -> actix_web::Error {
+    Error::invalid_input(field, error.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_anyhow() {
+        let error: Error = anyhow::Error::new(device::Error::Timeout).into();
+        assert_eq!(error.code, ErrorCode::DeviceTimeout);
+        assert_eq!(error.status_code(), StatusCode::GATEWAY_TIMEOUT);
+
+        let error: Error = anyhow::Error::new(device::Error::NotConnected("gone".to_string()))
+            .context("Failed to open serial port")
+            .into();
+        assert_eq!(error.code, ErrorCode::DeviceNotConnected);
+
+        let error: Error = anyhow::Error::new(validate::Error(vec![validate::Problem {
+            index: 1,
+            field: "name",
+            message: "Special net GND (index: 1) cannot be renamed".to_string(),
+        }]))
+        .into();
+        assert_eq!(error.code, ErrorCode::ValidationFailed);
+        assert_eq!(error.details[0].index, Some(1));
+        assert_eq!(error.details[0].field, "name");
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{
+    device::Device,
+    logger::DeviceLogger,
+    state::BoardState,
+    types::{Bridgelist, Net, SupplySwitchPos},
+};
+use actix_web::{get, rt, web, HttpRequest, HttpResponse, Result};
+use actix_ws::Message;
+use log::{debug, warn};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::{actor::DeviceActor, Shared};
+
+/// Number of events buffered for each subscriber, before it starts lagging behind
+const CHANNEL_CAPACITY: usize = 64;
+
+/// An event pushed to all clients connected to `/events`
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    DeviceConnected,
+    DeviceDisconnected,
+    NetlistChanged {
+        netlist: Vec<Net>,
+    },
+    BridgelistChanged {
+        bridgelist: Bridgelist,
+    },
+    SupplySwitchChanged {
+        pos: SupplySwitchPos,
+    },
+    ValidationFailed {
+        error: String,
+    },
+    /// Files in the UI directory (`--ui-dir`) changed
+    UiChanged,
+    LeaseAcquired {
+        holder: String,
+        expires_at: String,
+    },
+    LeaseReleased {
+        holder: String,
+    },
+    LeaseExpired {
+        holder: String,
+    },
+}
+
+/// Last known state of the board, used to only publish actual changes
+#[derive(Default)]
+struct Known {
+    connected: Option<bool>,
+    netlist: Option<Vec<Net>>,
+    bridgelist: Option<Bridgelist>,
+    supply_switch: Option<SupplySwitchPos>,
+}
+
+/// Distributes [`Event`]s to subscribers
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+    known: Mutex<Known>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            sender,
+            known: Mutex::new(Known::default()),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Returns true if at least one client is listening for events
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    pub fn publish(&self, event: Event) {
+        debug!("Publishing event: {:?}", event);
+        // sending only fails if there are no subscribers, which is fine.
+        _ = self.sender.send(event);
+    }
+
+    /// Record connection status, publishing an event if it changed
+    pub fn set_connected(&self, connected: bool) {
+        let changed = {
+            let mut known = self.known.lock().unwrap();
+            let changed = known.connected != Some(connected);
+            if changed && !connected {
+                // state must be re-read after reconnecting
+                *known = Known::default();
+            }
+            known.connected = Some(connected);
+            changed
+        };
+        if changed {
+            self.publish(if connected {
+                Event::DeviceConnected
+            } else {
+                Event::DeviceDisconnected
+            });
+        }
+    }
+
+    /// Read current state from the device, and publish events for everything that changed
+    pub fn sync<L: DeviceLogger>(&self, device: &mut Device<L>) -> anyhow::Result<BoardState> {
+        let state = BoardState::read(device)?;
+        self.update(state.clone());
+        Ok(state)
+    }
+
+    /// Record the given state, publishing events for everything that changed
+    fn update(&self, state: BoardState) {
+        let BoardState {
+            netlist,
+            bridgelist,
+            supply_switch,
+        } = state;
+
+        let mut events = vec![];
+        {
+            let mut known = self.known.lock().unwrap();
+            if known.netlist.as_ref() != Some(&netlist) {
+                known.netlist = Some(netlist.clone());
+                events.push(Event::NetlistChanged { netlist });
+            }
+            if known.bridgelist.as_ref() != Some(&bridgelist) {
+                known.bridgelist = Some(bridgelist.clone());
+                events.push(Event::BridgelistChanged { bridgelist });
+            }
+            if known.supply_switch != Some(supply_switch) {
+                known.supply_switch = Some(supply_switch);
+                events.push(Event::SupplySwitchChanged { pos: supply_switch });
+            }
+        }
+        for event in events {
+            self.publish(event);
+        }
+    }
+}
+
+/// Poll the device for changes, while there are subscribers.
+///
+/// This picks up changes that were not made through the HTTP API (e.g. by the CLI, or on the board itself).
+/// Polling is skipped while other requests are waiting for the device.
+pub fn poll(device: DeviceActor, events: Arc<Events>, interval: std::time::Duration) {
+    loop {
+        std::thread::sleep(interval);
+        if !events.has_subscribers() || device.queue_depth() > 0 {
+            continue;
+        }
+        let events = Arc::clone(&events);
+        let result = device.run_blocking(move |device_manager| {
+            let result = device_manager.with_device(|device| events.sync(device));
+            if let Err(err) = &result {
+                warn!("Polling device failed: {}", err);
+            }
+            events.set_connected(result.is_ok());
+        });
+        if result.is_err() {
+            // actor is gone, nothing left to poll
+            return;
+        }
+    }
+}
+
+#[get("/events")]
+async fn get_events(
+    req: HttpRequest,
+    body: web::Payload,
+    shared: web::Data<Shared>,
+) -> Result<HttpResponse> {
+    // browsers let any page open WebSockets, and CORS does not apply to them
+    shared.origins.check(&req)?;
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    let mut receiver = shared.events.subscribe();
+
+    rt::spawn(async move {
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        let text = serde_json::to_string(&event).expect("serialize event");
+                        if session.text(text).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("Event subscriber lagging behind, skipped {} events", count);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                message = messages.recv() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(reason))) => {
+                        _ = session.close(reason).await;
+                        return;
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None => break,
+                },
+            }
+        }
+        _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Node;
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    fn state(bridgelist: Bridgelist) -> BoardState {
+        BoardState {
+            netlist: vec![],
+            bridgelist,
+            supply_switch: SupplySwitchPos::V5,
+        }
+    }
+
+    /// Types of the events received so far
+    fn received(receiver: &mut broadcast::Receiver<Event>) -> Vec<String> {
+        let mut types = vec![];
+        loop {
+            match receiver.try_recv() {
+                Ok(event) => {
+                    let event = serde_json::to_value(event).unwrap();
+                    types.push(event["type"].as_str().unwrap().to_string());
+                }
+                Err(TryRecvError::Empty) => return types,
+                Err(err) => panic!("{}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_publishes_changes() {
+        let events = Events::new();
+        let mut receiver = events.subscribe();
+
+        events.update(state(vec![]));
+        assert_eq!(
+            received(&mut receiver),
+            vec![
+                "netlist_changed",
+                "bridgelist_changed",
+                "supply_switch_changed"
+            ]
+        );
+
+        events.update(state(vec![]));
+        assert!(received(&mut receiver).is_empty());
+
+        events.update(state(vec![(Node::Column(3), Node::Column(7))]));
+        assert_eq!(received(&mut receiver), vec!["bridgelist_changed"]);
+    }
+
+    #[test]
+    fn test_set_connected() {
+        let events = Events::new();
+        let mut receiver = events.subscribe();
+
+        events.set_connected(true);
+        events.set_connected(true);
+        assert_eq!(received(&mut receiver), vec!["device_connected"]);
+
+        events.update(state(vec![]));
+        received(&mut receiver);
+
+        // after reconnecting, the whole state is published again
+        events.set_connected(false);
+        events.set_connected(false);
+        events.set_connected(true);
+        events.update(state(vec![]));
+        assert_eq!(
+            received(&mut receiver),
+            vec![
+                "device_disconnected",
+                "device_connected",
+                "netlist_changed",
+                "bridgelist_changed",
+                "supply_switch_changed"
+            ]
+        );
+    }
+}
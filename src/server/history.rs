@@ -0,0 +1,156 @@
+use crate::state::BoardState;
+use serde::Serialize;
+use std::collections::VecDeque;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use utoipa::ToSchema;
+
+/// A state of the board, as recorded in the [`History`]
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Entry {
+    pub id: u64,
+    /// When the state was recorded (RFC 3339)
+    pub time: String,
+    /// What led to this state (e.g. `PUT /nets`, `restore #3`)
+    pub cause: String,
+    pub state: BoardState,
+}
+
+/// Bounded list of board states, with a pointer to the current one.
+///
+/// Applying a change records a new entry after the current one, discarding any entries that could have been redone.
+/// Undo and redo only move the pointer.
+pub struct History {
+    entries: VecDeque<Entry>,
+    /// Index of the entry matching the board's state
+    current: usize,
+    next_id: u64,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            current: 0,
+            next_id: 1,
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    /// ID of the entry matching the board's state, if any
+    pub fn current(&self) -> Option<u64> {
+        self.entries.get(self.current).map(|entry| entry.id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Take note of the state the board is in, before making a change.
+    ///
+    /// If it differs from the current entry, the board was changed some other way (e.g. via serial port,
+    /// or on the board itself). That state is recorded as well, so it can be returned to.
+    pub fn observe(&mut self, state: BoardState) {
+        let cause = if self.entries.is_empty() {
+            "initial state"
+        } else {
+            "external change"
+        };
+        self.record(cause, state);
+    }
+
+    /// Record the state of the board after a change. Does nothing if the state did not change.
+    pub fn record(&mut self, cause: &str, state: BoardState) {
+        if self.entries.get(self.current).map(|entry| &entry.state) == Some(&state) {
+            return;
+        }
+        if !self.entries.is_empty() {
+            self.entries.truncate(self.current + 1);
+        }
+        self.entries.push_back(Entry {
+            id: self.next_id,
+            time: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            cause: cause.to_string(),
+            state,
+        });
+        self.next_id += 1;
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+        self.current = self.entries.len() - 1;
+    }
+
+    /// Entry to return to, when undoing the last change
+    pub fn undo_target(&self) -> Option<&Entry> {
+        self.current
+            .checked_sub(1)
+            .and_then(|index| self.entries.get(index))
+    }
+
+    /// Entry to return to, when redoing the last undone change
+    pub fn redo_target(&self) -> Option<&Entry> {
+        self.entries.get(self.current + 1)
+    }
+
+    /// Mark the entry with the given ID as current, after its state was applied
+    pub fn move_to(&mut self, id: u64) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.id == id) {
+            self.current = index;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Node, SupplySwitchPos};
+
+    fn state(bridges: usize) -> BoardState {
+        BoardState {
+            netlist: vec![],
+            bridgelist: (1..=bridges as u8)
+                .map(|i| (Node::Column(i), Node::Column(i + 30)))
+                .collect(),
+            supply_switch: SupplySwitchPos::V5,
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new(3);
+        assert!(history.undo_target().is_none());
+
+        history.observe(state(0));
+        history.record("add", state(1));
+        history.record("add", state(2));
+        // unchanged
+        history.record("add", state(2));
+        assert_eq!(history.current(), Some(3));
+
+        assert_eq!(history.undo_target().unwrap().id, 2);
+        history.move_to(2);
+        assert_eq!(history.redo_target().unwrap().id, 3);
+
+        // a new change discards the redo entry
+        history.record("clear", state(0));
+        assert!(history.redo_target().is_none());
+        assert_eq!(
+            history.entries().map(|entry| entry.id).collect::<Vec<_>>(),
+            vec![1, 2, 4]
+        );
+
+        // oldest entries are dropped
+        history.record("add", state(3));
+        assert_eq!(
+            history.entries().map(|entry| entry.id).collect::<Vec<_>>(),
+            vec![2, 4, 5]
+        );
+        assert_eq!(history.current(), Some(5));
+    }
+}
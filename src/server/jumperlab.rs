@@ -1,58 +1,403 @@
 use actix_web::{
     dev::{ServiceFactory, ServiceRequest},
     get,
-    web::{self, Data},
-    App, HttpResponse, Responder, Result,
+    http::{
+        header::{self, EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch},
+        StatusCode,
+    },
+    web, App, HttpRequest, HttpResponse, Result,
 };
+use log::{info, warn};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::events::{Event, Events};
+
+#[cfg(feature = "jumperlab")]
 use std::io::{Cursor, Read};
-use std::sync::{Arc, Mutex};
-use zip::read::ZipArchive;
+#[cfg(feature = "jumperlab")]
+use std::sync::Mutex;
+#[cfg(feature = "jumperlab")]
+use zip::{read::ZipArchive, result::ZipError};
 
 // Embed ZIP archive containing Jumperlab's assets
+#[cfg(feature = "jumperlab")]
 const ZIP: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/jumperlab.zip"));
 
+/// `Cache-Control` for files that never change
+#[cfg(feature = "jumperlab")]
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Interval in which `--ui-dir` is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Reloads the page when the UI directory changes. Injected into HTML pages served from `--ui-dir`.
+const RELOAD_SCRIPT: &str = r#"<script>
+(() => {
+  const url = new URL("/events", location.href);
+  url.protocol = url.protocol.replace("http", "ws");
+  const token = new URLSearchParams(location.search).get("access_token");
+  if (token) url.searchParams.set("access_token", token);
+  new WebSocket(url).onmessage = (message) => {
+    if (JSON.parse(message.data).type === "ui_changed") location.reload();
+  };
+})();
+</script>
+"#;
+
+/// Where Jumperlab's assets are served from
+pub enum Assets {
+    /// ZIP archive embedded at build time
+    #[cfg(feature = "jumperlab")]
+    Embedded(Mutex<ZipArchive<Cursor<&'static [u8]>>>),
+    /// Directory on disk (`--ui-dir`), for working on jumperlab itself
+    Dir(PathBuf),
+}
+
+/// A file from [`Assets`]
+struct Asset {
+    contents: Vec<u8>,
+    etag: EntityTag,
+    last_modified: Option<SystemTime>,
+}
+
+impl Assets {
+    /// Serve from `ui_dir` if given, otherwise from the embedded archive (if any)
+    pub fn new(ui_dir: Option<PathBuf>) -> Option<Self> {
+        if let Some(dir) = ui_dir {
+            return Some(Assets::Dir(dir));
+        }
+        #[cfg(feature = "jumperlab")]
+        return Some(Assets::Embedded(Mutex::new(
+            ZipArchive::new(Cursor::new(ZIP)).unwrap(),
+        )));
+        #[allow(unreachable_code)]
+        None
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        match self {
+            #[cfg(feature = "jumperlab")]
+            Assets::Embedded(archive) => {
+                let mut archive = archive.lock().unwrap();
+                let found = archive.by_name(path).map(|entry| entry.is_file());
+                found.unwrap_or(false)
+            }
+            Assets::Dir(root) => root.join(path).is_file(),
+        }
+    }
+
+    fn read(&self, path: &str) -> std::io::Result<Option<Asset>> {
+        match self {
+            #[cfg(feature = "jumperlab")]
+            Assets::Embedded(archive) => {
+                let mut archive = archive.lock().unwrap();
+                let mut entry = match archive.by_name(path) {
+                    Ok(entry) if entry.is_file() => entry,
+                    Ok(_) | Err(ZipError::FileNotFound) => return Ok(None),
+                    Err(err) => return Err(err.into()),
+                };
+                let mut contents = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut contents)?;
+                Ok(Some(Asset {
+                    contents,
+                    etag: EntityTag::new_strong(format!("{:08x}", entry.crc32())),
+                    last_modified: entry.last_modified().to_time().ok().map(SystemTime::from),
+                }))
+            }
+            Assets::Dir(root) => {
+                let file = root.join(path);
+                let metadata = match std::fs::metadata(&file) {
+                    Ok(metadata) if metadata.is_file() => metadata,
+                    Ok(_) => return Ok(None),
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                    Err(err) => return Err(err),
+                };
+                let last_modified = metadata.modified().ok();
+                let mtime = last_modified
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                Ok(Some(Asset {
+                    contents: std::fs::read(&file)?,
+                    etag: EntityTag::new_strong(format!(
+                        "{:x}-{:x}",
+                        metadata.len(),
+                        mtime.as_nanos()
+                    )),
+                    last_modified,
+                }))
+            }
+        }
+    }
+}
+
 // Install `/jumperlab/*` route
-pub fn add_to_app<T>(app: App<T>) -> App<T>
+pub fn add_to_app<T>(app: App<T>, assets: web::Data<Assets>) -> App<T>
 where
     T: ServiceFactory<ServiceRequest, Config = (), Error = actix_web::Error, InitError = ()>,
 {
-    let archive = Arc::new(Mutex::new(ZipArchive::new(Cursor::new(ZIP)).unwrap()));
-    app.app_data(Data::new(archive))
+    app.app_data(assets)
         .service(handle_index)
         .service(handle_file)
 }
 
 #[get("/jumperlab")]
-async fn handle_index(
-    data: web::Data<Arc<Mutex<ZipArchive<Cursor<&[u8]>>>>>,
-) -> Result<impl Responder> {
-    serve_path("index.html", data)
+async fn handle_index(req: HttpRequest, assets: web::Data<Assets>) -> Result<HttpResponse> {
+    serve_path(&req, &assets, "index.html")
 }
 
 #[get("/jumperlab/{path}*")]
 async fn handle_file(
+    req: HttpRequest,
     path: web::Path<String>,
-    data: web::Data<Arc<Mutex<ZipArchive<Cursor<&[u8]>>>>>,
-) -> Result<impl Responder> {
-    serve_path(path.as_str(), data)
-}
-
-fn serve_path(
-    path: &str,
-    data: web::Data<Arc<Mutex<ZipArchive<Cursor<&[u8]>>>>>,
-) -> Result<impl Responder> {
-    let archive = data.into_inner();
-    let archive = &mut archive.lock().unwrap();
-    let response = if let Ok(entry) = archive.by_name(path).as_mut() {
-        let content_type = mime_guess::from_path(path)
-            .first()
-            .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM)
-            .to_string();
-        let mut buf = Vec::with_capacity(entry.size() as usize);
-        entry.read_to_end(&mut buf)?;
-        HttpResponse::Ok().content_type(content_type).body(buf)
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse> {
+    serve_path(&req, &assets, path.as_str())
+}
+
+/// Whether the path stays within the assets: only plain names, no `..`, root or drive (e.g. `C:`)
+fn is_relative_path(path: &str) -> bool {
+    !path.contains('\\')
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn serve_path(req: &HttpRequest, assets: &Assets, path: &str) -> Result<HttpResponse> {
+    if !is_relative_path(path) {
+        return Ok(HttpResponse::NotFound().body("Not found"));
+    }
+    let path = if path.is_empty() { "index.html" } else { path };
+    let path = if assets.contains(path) {
+        path
+    } else if !path.rsplit('/').next().unwrap_or(path).contains('.') {
+        // not a file, but (probably) a client-side route
+        "index.html"
     } else {
-        HttpResponse::NotFound().body("Not found")
+        return Ok(HttpResponse::NotFound().body("Not found"));
     };
-    Ok(response)
+
+    let content_type = mime_guess::from_path(path)
+        .first()
+        .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM)
+        .to_string();
+    let mut response = HttpResponse::Ok();
+    response.content_type(content_type);
+
+    let asset = match assets {
+        #[cfg(feature = "jumperlab")]
+        Assets::Embedded(_) => {
+            response
+                .insert_header((
+                    header::CACHE_CONTROL,
+                    if is_hashed(path) {
+                        IMMUTABLE
+                    } else {
+                        "no-cache"
+                    },
+                ))
+                .insert_header((header::VARY, "Accept-Encoding"));
+            let accept_encoding = req
+                .headers()
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|value| value.to_str().ok());
+            let mut precompressed = None;
+            for (encoding, extension) in [("br", "br"), ("gzip", "gz")] {
+                if !accepts_encoding(accept_encoding, encoding) {
+                    continue;
+                }
+                if let Some(asset) = assets.read(&format!("{}.{}", path, extension))? {
+                    response.insert_header((header::CONTENT_ENCODING, encoding));
+                    precompressed = Some(asset);
+                    break;
+                }
+            }
+            match precompressed {
+                Some(asset) => Some(asset),
+                None => assets.read(path)?,
+            }
+        }
+        Assets::Dir(_) => {
+            response.insert_header((header::CACHE_CONTROL, "no-cache"));
+            assets.read(path)?.map(|mut asset| {
+                if path.ends_with(".html") {
+                    asset.contents = inject_reload_script(asset.contents);
+                }
+                asset
+            })
+        }
+    };
+    // file disappeared since checking for it
+    let Some(asset) = asset else {
+        return Ok(HttpResponse::NotFound().body("Not found"));
+    };
+
+    response.insert_header(header::ETag(asset.etag.clone()));
+    if let Some(last_modified) = asset.last_modified {
+        response.insert_header(header::LastModified(HttpDate::from(last_modified)));
+    }
+    if is_fresh(req, &asset) {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+    }
+    Ok(response.body(asset.contents))
+}
+
+/// Check if the client's cached copy (as indicated by `If-None-Match` / `If-Modified-Since`) is still valid
+fn is_fresh(req: &HttpRequest, asset: &Asset) -> bool {
+    if req.headers().contains_key(header::IF_NONE_MATCH) {
+        return match IfNoneMatch::parse(req).unwrap_or(IfNoneMatch::Items(vec![])) {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(&asset.etag)),
+        };
+    }
+    let seconds = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    };
+    match (IfModifiedSince::parse(req).ok(), asset.last_modified) {
+        (Some(IfModifiedSince(since)), Some(modified)) => {
+            seconds(modified) <= seconds(SystemTime::from(since))
+        }
+        _ => false,
+    }
+}
+
+/// Check if a file name contains a content hash, as produced by bundlers (e.g. `main.3f9a8c1d.js`).
+///
+/// Such files are never modified, only replaced by ones with a different name, so they can be cached forever.
+#[cfg_attr(not(feature = "jumperlab"), allow(dead_code))]
+fn is_hashed(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let Some((stem, _extension)) = name.rsplit_once('.') else {
+        return false;
+    };
+    stem.split(['.', '-']).skip(1).any(|part| {
+        part.len() >= 8
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && part.chars().any(|c| c.is_ascii_digit())
+    })
+}
+
+/// Check if the given `Accept-Encoding` header allows the given encoding
+#[cfg_attr(not(feature = "jumperlab"), allow(dead_code))]
+fn accepts_encoding(accept_encoding: Option<&str>, encoding: &str) -> bool {
+    accept_encoding.unwrap_or_default().split(',').any(|item| {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        let rejected = parts.any(|param| {
+            let quality = param.trim().strip_prefix("q=");
+            quality.and_then(|q| q.trim().parse::<f32>().ok()) == Some(0.0)
+        });
+        name.eq_ignore_ascii_case(encoding) && !rejected
+    })
+}
+
+fn inject_reload_script(mut html: Vec<u8>) -> Vec<u8> {
+    let position = html
+        .windows(7)
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+    html.splice(position..position, RELOAD_SCRIPT.bytes());
+    html
+}
+
+/// Watch the UI directory, and publish [`Event::UiChanged`] after files in it changed.
+///
+/// Uses polling, to not depend on platform specific file notifications. The event is only
+/// published once the directory stopped changing, so a rebuild only causes a single reload.
+pub fn watch(root: PathBuf, events: Arc<Events>) {
+    let mut last = fingerprint(&root);
+    let mut changed = false;
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+        let current = fingerprint(&root);
+        if current != last {
+            last = current;
+            changed = true;
+        } else if changed {
+            changed = false;
+            info!("UI directory changed, reloading");
+            events.publish(Event::UiChanged);
+        }
+    }
+}
+
+/// Check that `dir` can be served, warning about a missing `index.html`
+pub fn check_dir(dir: &Path) -> std::io::Result<()> {
+    if !dir.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("UI directory {:?} does not exist", dir),
+        ));
+    }
+    if !dir.join("index.html").is_file() {
+        warn!("UI directory {:?} does not contain an index.html", dir);
+    }
+    Ok(())
+}
+
+/// Hash of names, sizes and modification times of all files below `dir`
+fn fingerprint(dir: &Path) -> u64 {
+    fn hash_dir(dir: &Path, hasher: &mut DefaultHasher) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            entry.file_name().hash(hasher);
+            if metadata.is_dir() {
+                hash_dir(&entry.path(), hasher);
+            } else {
+                metadata.len().hash(hasher);
+                metadata.modified().ok().hash(hasher);
+            }
+        }
+    }
+    let mut hasher = DefaultHasher::new();
+    hash_dir(dir, &mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_hashed() {
+        assert!(is_hashed("static/js/main.3f9a8c1d.js"));
+        assert!(is_hashed("static/js/787.2b5c91e0.chunk.js"));
+        assert!(is_hashed("assets/index-Bx9k2aQp.css"));
+        assert!(!is_hashed("index.html"));
+        assert!(!is_hashed("jumperlab-logo.svg"));
+        assert!(!is_hashed("manifest.json"));
+    }
+
+    #[test]
+    fn test_is_relative_path() {
+        assert!(is_relative_path(""));
+        assert!(is_relative_path("index.html"));
+        assert!(is_relative_path("static/js/main.3f9a8c1d.js"));
+        assert!(!is_relative_path("../secret"));
+        assert!(!is_relative_path("static/../../secret"));
+        assert!(!is_relative_path("/etc/passwd"));
+        assert!(!is_relative_path("..\\secret"));
+        #[cfg(windows)]
+        assert!(!is_relative_path("C:/Windows/win.ini"));
+    }
+
+    #[test]
+    fn test_accepts_encoding() {
+        assert!(accepts_encoding(Some("gzip, deflate, br"), "br"));
+        assert!(accepts_encoding(Some("br;q=1.0, gzip;q=0.8"), "gzip"));
+        assert!(!accepts_encoding(Some("gzip, br;q=0"), "br"));
+        assert!(!accepts_encoding(None, "gzip"));
+    }
 }
@@ -0,0 +1,278 @@
+use actix_web::{dev::ServiceRequest, http::header::HeaderMap};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use utoipa::ToSchema;
+
+use super::auth::{constant_time_eq, Role};
+use super::error::{Error, ErrorCode};
+
+/// Header used to present a lease token
+pub const LEASE_HEADER: &str = "X-Lease-Token";
+
+/// Public information about a lease
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct LeaseInfo {
+    /// Who holds the lease, as given when acquiring it
+    pub holder: String,
+    /// When the lease ends, unless it is renewed (RFC 3339)
+    pub expires_at: String,
+}
+
+/// Response to acquiring (or renewing) a lease
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Granted {
+    /// Must be sent in the `X-Lease-Token` header, with every write request
+    pub token: String,
+    #[serde(flatten)]
+    pub lease: LeaseInfo,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LeaseRequest {
+    /// Name shown to others, e.g. `alice` or `ci-job-1234`
+    pub holder: String,
+    /// Duration in seconds
+    pub duration: u64,
+}
+
+struct Lease {
+    token: String,
+    holder: String,
+    expires: SystemTime,
+}
+
+impl Lease {
+    fn info(&self) -> LeaseInfo {
+        LeaseInfo {
+            holder: self.holder.clone(),
+            expires_at: format_time(self.expires),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    current: Option<Lease>,
+    /// The last lease that was cleared because it expired, until its expiry is announced via [`Leases::expire`]
+    expired: Option<Lease>,
+}
+
+impl State {
+    fn clear_expired(&mut self) {
+        if matches!(&self.current, Some(lease) if lease.expires <= SystemTime::now()) {
+            self.expired = self.current.take();
+        }
+    }
+}
+
+/// Exclusive, time-limited claims on the board.
+///
+/// While a lease is active, write requests are only accepted if they carry its token. Reads are always allowed.
+pub struct Leases {
+    state: Mutex<State>,
+    max_duration: Duration,
+}
+
+impl Leases {
+    pub fn new(max_duration: Duration) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            max_duration,
+        }
+    }
+
+    /// The active lease, if any
+    pub fn info(&self) -> Option<LeaseInfo> {
+        let mut state = self.state.lock().unwrap();
+        state.clear_expired();
+        state.current.as_ref().map(Lease::info)
+    }
+
+    /// Check if the given request may proceed.
+    ///
+    /// Write requests fail with [`ErrorCode::Locked`], while someone else holds the lease.
+    pub fn check(&self, req: &ServiceRequest) -> Result<(), Error> {
+        if Role::required_for(req.method()) == Role::ReadOnly {
+            return Ok(());
+        }
+        let mut state = self.state.lock().unwrap();
+        state.clear_expired();
+        match state.current.as_ref() {
+            Some(lease) if !has_token(lease, token(req.headers())) => Err(Error::new(
+                ErrorCode::Locked,
+                format!(
+                    "Board in use by {} until {}",
+                    lease.holder,
+                    format_time(lease.expires)
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Acquire a lease, or renew it if the request carries the token of the active one.
+    ///
+    /// Other holders are already rejected by [`Leases::check`].
+    pub fn acquire(&self, headers: &HeaderMap, request: LeaseRequest) -> Result<Granted, Error> {
+        if request.holder.trim().is_empty() {
+            return Err(Error::invalid_input("holder", "Must not be empty"));
+        }
+        let duration = Duration::from_secs(request.duration);
+        if duration.is_zero() || duration > self.max_duration {
+            return Err(Error::invalid_input(
+                "duration",
+                format!(
+                    "Must be between 1 and {} seconds",
+                    self.max_duration.as_secs()
+                ),
+            ));
+        }
+        let mut state = self.state.lock().unwrap();
+        state.clear_expired();
+        let token = match state.current.as_ref() {
+            Some(lease) if has_token(lease, token(headers)) => lease.token.clone(),
+            _ => generate_token(),
+        };
+        let lease = Lease {
+            token: token.clone(),
+            holder: request.holder,
+            expires: SystemTime::now() + duration,
+        };
+        let info = lease.info();
+        state.current = Some(lease);
+        Ok(Granted { token, lease: info })
+    }
+
+    /// Release the active lease, returning it
+    pub fn release(&self) -> Option<LeaseInfo> {
+        let mut state = self.state.lock().unwrap();
+        state.clear_expired();
+        state.current.take().as_ref().map(Lease::info)
+    }
+
+    /// Release the lease with the given token, if it is expired. Returns the released lease.
+    ///
+    /// Also returns the lease if it was already cleared by another call after expiring, so its expiry is still announced.
+    pub fn expire(&self, token: &str) -> Option<LeaseInfo> {
+        let mut state = self.state.lock().unwrap();
+        state.clear_expired();
+        match state.expired.as_ref() {
+            Some(lease) if has_token(lease, Some(token)) => {
+                state.expired.take().as_ref().map(Lease::info)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn has_token(lease: &Lease, token: Option<&str>) -> bool {
+    token.is_some_and(|token| constant_time_eq(token, &lease.token))
+}
+
+fn token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(LEASE_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Generate an unguessable token: 128 bits from the operating system's random number generator, hex-encoded
+fn generate_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)
+        .expect("read from the operating system's random number generator");
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn format_time(time: SystemTime) -> String {
+    OffsetDateTime::from(time)
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::Method, test::TestRequest};
+
+    fn request(leases: &Leases, method: Method, token: Option<&str>) -> Option<ErrorCode> {
+        let mut req = TestRequest::default().method(method).uri("/bridges");
+        if let Some(token) = token {
+            req = req.insert_header((LEASE_HEADER, token));
+        }
+        leases.check(&req.to_srv_request()).err().map(|e| e.code)
+    }
+
+    #[test]
+    fn test_lease() {
+        let leases = Leases::new(Duration::from_secs(60));
+        assert_eq!(request(&leases, Method::POST, None), None);
+
+        let granted = leases
+            .acquire(
+                &HeaderMap::new(),
+                LeaseRequest {
+                    holder: "alice".to_string(),
+                    duration: 30,
+                },
+            )
+            .unwrap();
+        assert_eq!(leases.info().unwrap().holder, "alice");
+        assert_eq!(request(&leases, Method::GET, None), None);
+        assert_eq!(
+            request(&leases, Method::POST, None),
+            Some(ErrorCode::Locked)
+        );
+        assert_eq!(
+            request(&leases, Method::POST, Some("wrong")),
+            Some(ErrorCode::Locked)
+        );
+        assert_eq!(request(&leases, Method::POST, Some(&granted.token)), None);
+
+        assert!(leases
+            .acquire(
+                &HeaderMap::new(),
+                LeaseRequest {
+                    holder: "bob".to_string(),
+                    duration: 3600,
+                },
+            )
+            .is_err());
+
+        assert_eq!(leases.release().unwrap().holder, "alice");
+        assert_eq!(request(&leases, Method::POST, None), None);
+    }
+
+    #[test]
+    fn test_expire() {
+        let leases = Leases::new(Duration::from_secs(60));
+        let granted = leases
+            .acquire(
+                &HeaderMap::new(),
+                LeaseRequest {
+                    holder: "alice".to_string(),
+                    duration: 1,
+                },
+            )
+            .unwrap();
+        assert!(leases.expire(&granted.token).is_none());
+
+        let mut state = leases.state.lock().unwrap();
+        state.current.as_mut().unwrap().expires = SystemTime::now();
+        drop(state);
+        // cleared by a request, before the expiry is announced
+        assert!(leases.info().is_none());
+        assert!(leases.expire("wrong").is_none());
+        assert_eq!(leases.expire(&granted.token).unwrap().holder, "alice");
+        assert!(leases.expire(&granted.token).is_none());
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    device_manager::{FoundPort, PortRole, Status},
+    diff::Change,
+    snapshot::{Snapshot, Summary},
+    state::BoardState,
+    types::{ChipStatus, Color, Net, NetPatch, NewNet, Node, SupplySwitchPos, NAMED_NODES},
+};
+use actix_web::{get, web, Responder};
+use serde_json::json;
+use utoipa::{
+    openapi::{
+        schema::{ArrayBuilder, ObjectBuilder, OneOfBuilder, Schema, SchemaType},
+        RefOr,
+    },
+    OpenApi, ToSchema,
+};
+
+use super::error::{Detail, Error, ErrorCode};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(description = "HTTP API of jlctl, to control a Jumperless breadboard"),
+    paths(
+        super::get_status,
+        super::get_nets,
+        super::put_nets,
+        super::get_net,
+        super::post_net,
+        super::patch_net,
+        super::delete_net,
+        super::get_supply_switch_pos,
+        super::set_supply_switch_pos,
+        super::get_chipstatus,
+        super::get_bridges,
+        super::put_bridges,
+        super::add_bridges,
+        super::remove_bridges,
+        super::clear_bridges,
+        super::get_graph,
+        super::put_light,
+        super::post_raw,
+        super::get_ports,
+        super::put_port,
+        super::get_metrics,
+        super::get_history,
+        super::post_undo,
+        super::post_redo,
+        super::post_restore,
+        super::get_state,
+        super::get_snapshots,
+        super::get_snapshot,
+        super::put_snapshot,
+        super::post_snapshot_restore,
+        super::get_lease,
+        super::post_lease,
+        super::delete_lease,
+    ),
+    components(schemas(
+        Net,
+        NewNet,
+        NetPatch,
+        Node,
+        Bridge,
+        Color,
+        SupplySwitchPos,
+        ChipStatus,
+        Status,
+        FoundPort,
+        PortRole,
+        super::StatusResponse,
+        super::RawInstruction,
+        super::RawResponse,
+        super::SelectPort,
+        super::GraphFormat,
+        super::HistoryResponse,
+        super::history::Entry,
+        BoardState,
+        Snapshot,
+        Summary,
+        Change,
+        super::RestoreResponse,
+        super::lease::LeaseInfo,
+        super::lease::LeaseRequest,
+        super::lease::Granted,
+        Error,
+        ErrorCode,
+        Detail,
+    ))
+)]
+struct ApiDoc;
+
+#[get("/openapi.json")]
+async fn get_openapi() -> impl Responder {
+    web::Json(ApiDoc::openapi())
+}
+
+// `Node`, `Color` and `SupplySwitchPos` have custom serde implementations, so their schemas are spelled out by hand.
+
+impl<'s> ToSchema<'s> for Node {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let names: Vec<String> = NAMED_NODES.iter().map(|node| node.to_string()).collect();
+        (
+            "Node",
+            OneOfBuilder::new()
+                .item(
+                    ObjectBuilder::new()
+                        .schema_type(SchemaType::Integer)
+                        .minimum(Some(1.0))
+                        .maximum(Some(60.0))
+                        .description(Some("Breadboard column")),
+                )
+                .item(
+                    ObjectBuilder::new()
+                        .schema_type(SchemaType::String)
+                        .enum_values(Some(names))
+                        .description(Some("Named node")),
+                )
+                .example(Some(json!("GND")))
+                .into(),
+        )
+    }
+}
+
+impl<'s> ToSchema<'s> for Color {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "Color",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .pattern(Some("^#[0-9a-fA-F]{6}$"))
+                .example(Some(json!("#ff0000")))
+                .into(),
+        )
+    }
+}
+
+impl<'s> ToSchema<'s> for SupplySwitchPos {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "SupplySwitchPos",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .enum_values(Some(["8V", "3.3V", "5V"]))
+                .into(),
+        )
+    }
+}
+
+/// A bridge between two nodes, serialized as a two-element array
+pub struct Bridge;
+
+impl<'s> ToSchema<'s> for Bridge {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "Bridge",
+            ArrayBuilder::new()
+                .items(RefOr::Ref(utoipa::openapi::Ref::from_schema_name("Node")))
+                .min_items(Some(2))
+                .max_items(Some(2))
+                .example(Some(json!(["GND", 17])))
+                .into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(doc["paths"]["/nets/{index}"]["patch"].is_object());
+        assert!(doc["components"]["schemas"]["Net"].is_object());
+        assert_eq!(
+            doc["components"]["schemas"]["Node"]["oneOf"][1]["enum"][0],
+            json!("GND")
+        );
+    }
+}
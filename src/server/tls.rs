@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use log::info;
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+use std::io::{BufReader, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Where the server gets its certificate from
+pub enum Tls {
+    /// Load certificate (chain) and private key from the given PEM files
+    Files { cert: PathBuf, key: PathBuf },
+    /// Generate a self-signed certificate for the given host names.
+    ///
+    /// If `store` is given, the certificate is written to (cert, key) and reused on the next start,
+    /// so browsers only need to be told to trust it once.
+    SelfSigned {
+        hostnames: Vec<String>,
+        store: Option<(PathBuf, PathBuf)>,
+    },
+}
+
+impl Tls {
+    /// Host names for a self-signed certificate, when listening on `listen` (`<host>:<port>`):
+    /// `localhost`, `127.0.0.1` and the listen host, unless it is unspecified (`0.0.0.0` or `::`)
+    pub fn default_hostnames(listen: &str) -> Vec<String> {
+        let mut hostnames = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        let host = listen
+            .rsplit_once(':')
+            .map(|(host, _)| host.trim_start_matches('[').trim_end_matches(']'));
+        let unspecified = |host: &str| host.parse::<IpAddr>().is_ok_and(|ip| ip.is_unspecified());
+        if let Some(host) = host {
+            // IP addresses become IP address entries of the certificate (see `rcgen::CertificateParams::new`)
+            if !host.is_empty() && !unspecified(host) && !hostnames.iter().any(|h| h == host) {
+                hostnames.push(host.to_string());
+            }
+        }
+        hostnames
+    }
+
+    pub fn server_config(&self) -> Result<ServerConfig> {
+        let (cert_pem, key_pem) = match self {
+            Tls::Files { cert, key } => (read(cert)?, read(key)?),
+            Tls::SelfSigned {
+                store: Some((cert, key)),
+                ..
+            } if cert.exists() && key.exists() => {
+                info!("Using previously generated certificate {:?}", cert);
+                (read(cert)?, read(key)?)
+            }
+            Tls::SelfSigned { hostnames, store } => {
+                let (cert_pem, key_pem) = self_signed(hostnames)?;
+                if let Some((cert, key)) = store {
+                    info!(
+                        "Storing self-signed certificate in {:?} and {:?}",
+                        cert, key
+                    );
+                    std::fs::write(cert, &cert_pem)
+                        .with_context(|| format!("Failed to write {:?}", cert))?;
+                    write_private(key, &key_pem)
+                        .with_context(|| format!("Failed to write {:?}", key))?;
+                }
+                (cert_pem, key_pem)
+            }
+        };
+
+        let mut certs = vec![];
+        let mut key = None;
+        for item in rustls_pemfile::read_all(&mut BufReader::new(cert_pem.as_bytes()))?
+            .into_iter()
+            .chain(rustls_pemfile::read_all(&mut BufReader::new(
+                key_pem.as_bytes(),
+            ))?)
+        {
+            match item {
+                Item::X509Certificate(der) => certs.push(Certificate(der)),
+                Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => {
+                    key.get_or_insert(PrivateKey(der));
+                }
+                _ => {}
+            }
+        }
+        if certs.is_empty() {
+            return Err(anyhow::anyhow!("No certificate found"));
+        }
+        let key = key.ok_or_else(|| anyhow::anyhow!("No private key found"))?;
+
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("Invalid certificate or key")
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))
+}
+
+/// Write a file that only the current user can read
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+/// Generate a self-signed certificate, returning (certificate, private key) in PEM format
+fn self_signed(hostnames: &[String]) -> Result<(String, String)> {
+    info!(
+        "Generating self-signed certificate for {}",
+        hostnames.join(", ")
+    );
+    let cert = rcgen::generate_simple_self_signed(hostnames.to_vec())?;
+    Ok((cert.serialize_pem()?, cert.serialize_private_key_pem()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_signed() {
+        let tls = Tls::SelfSigned {
+            hostnames: vec!["localhost".to_string()],
+            store: None,
+        };
+        assert!(tls.server_config().is_ok());
+    }
+
+    #[test]
+    fn test_default_hostnames() {
+        let hostnames = |listen| Tls::default_hostnames(listen)[2..].to_vec();
+        assert_eq!(hostnames("192.168.1.5:8443"), vec!["192.168.1.5"]);
+        assert_eq!(hostnames("[::1]:8443"), vec!["::1"]);
+        assert_eq!(hostnames("jumperless.local:8443"), vec!["jumperless.local"]);
+        assert!(hostnames("localhost:8443").is_empty());
+        assert!(hostnames("0.0.0.0:8443").is_empty());
+        assert!(hostnames("[::]:8443").is_empty());
+    }
+}
@@ -0,0 +1,447 @@
+//! Interactive shell (`jlctl shell`), which keeps the connection to the board open between commands.
+//!
+//! Every line is parsed like the arguments of a one-shot invocation (e.g. `bridge add 3-7`),
+//! but only commands that operate on the board are accepted.
+
+use crate::{
+    board::Board, device_manager::DeviceManager, is_board_command, logger::FileLogger, run_command,
+    types::NAMED_NODES, Cli, Command,
+};
+use anyhow::Result;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use std::io::{BufRead, IsTerminal, Write};
+
+/// File where entered lines are kept, relative to the working directory
+pub const HISTORY_FILE: &str = ".jlctl/history";
+
+/// Number of lines kept in the history file
+const HISTORY_SIZE: usize = 1000;
+
+/// Top-level words that can be completed: commands handled by [`run_command`], and builtins
+const COMMANDS: &[&str] = &[
+    "apply",
+    "bridge",
+    "chip-status",
+    "exit",
+    "help",
+    "lightnet",
+    "net",
+    "raw",
+    "supply-switch-pos",
+];
+
+/// A line entered in the shell
+#[derive(Debug, Parser)]
+#[command(
+    no_binary_name = true,
+    disable_version_flag = true,
+    about = "Run commands on the board. Leave with `exit` or Ctrl-D."
+)]
+struct Line {
+    #[command(subcommand)]
+    command: Command,
+}
+
+impl Line {
+    fn parse_words(words: Vec<String>) -> Result<Self, clap::Error> {
+        // hide the commands that are not available, so they do not show up in `help`
+        let mut parser = Self::command();
+        let hidden: Vec<String> = parser
+            .get_subcommands()
+            .map(|command| command.get_name().to_string())
+            .filter(|name| !COMMANDS.contains(&name.as_str()))
+            .collect();
+        for name in hidden {
+            parser = parser.mut_subcommand(name, |command| command.hide(true));
+        }
+        Self::from_arg_matches(&parser.try_get_matches_from(words)?)
+    }
+}
+
+/// Where the shell sends commands to
+pub enum Target<'a> {
+    /// The device, via the serial port. It is reopened when the connection is lost.
+    Device(&'a mut DeviceManager<FileLogger>),
+    /// Any other board (e.g. a `jlctl server`, or a dry run), described by the given name
+    Board(&'a mut dyn Board, String),
+}
+
+impl Target<'_> {
+    fn prompt(&self) -> String {
+        let state = match self {
+            Target::Device(device_manager) => device_manager
+                .connected_port()
+                .unwrap_or_else(|| "disconnected".to_string()),
+            Target::Board(_, name) => name.clone(),
+        };
+        format!("jlctl [{}]> ", state)
+    }
+
+    fn run(&mut self, command: Command) -> Result<()> {
+        match self {
+            Target::Device(device_manager) => {
+                device_manager.with_device(|device| run_command(device, command))
+            }
+            Target::Board(board, _) => run_command(*board, command),
+        }
+    }
+}
+
+/// Read and run commands until `exit`, or the end of input
+pub fn run(mut target: Target) -> Result<()> {
+    if let Target::Device(device_manager) = &mut target {
+        if let Err(e) = device_manager.with_device(|_| Ok(())) {
+            log::warn!("Not connected: {:#}", e);
+        }
+    }
+    let mut editor = Editor::new(HISTORY_FILE);
+    while let Some(line) = editor.read_line(&target.prompt())? {
+        let Some(words) = shlex::split(&line) else {
+            eprintln!("Error: unterminated quote");
+            continue;
+        };
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("exit" | "quit") => break,
+            Some(_) => {}
+        }
+        let command = match Line::parse_words(words) {
+            Ok(Line { command }) if is_board_command(&command) => command,
+            Ok(_) => {
+                eprintln!("Error: this command is not available in the shell");
+                continue;
+            }
+            // also covers `help` and `--help`
+            Err(e) => {
+                e.print()?;
+                continue;
+            }
+        };
+        if let Err(e) = target.run(command) {
+            eprintln!("Error: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Complete the word at the end of `line`.
+///
+/// Returns where the completed part starts in `line`, and the candidates to replace it with.
+/// The first word completes to a command, later words to subcommands or node names.
+fn complete(line: &str) -> (usize, Vec<String>) {
+    let word_start = line
+        .char_indices()
+        .rfind(|(_, c)| c.is_whitespace())
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+    let word = &line[word_start..];
+    let mut previous = line[..word_start].split_whitespace();
+
+    let Some(first) = previous.next() else {
+        let candidates = COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(word))
+            .map(|command| command.to_string())
+            .collect();
+        return (word_start, candidates);
+    };
+
+    let cli = Cli::command();
+    let mut command = cli.find_subcommand(first);
+    let mut in_arguments = false;
+    for word in previous {
+        match command.and_then(|command| command.find_subcommand(word)) {
+            Some(subcommand) => command = Some(subcommand),
+            None => in_arguments = true,
+        }
+    }
+    if let Some(command) = command.filter(|command| command.has_subcommands() && !in_arguments) {
+        let candidates = command
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name())
+            .filter(|name| name.starts_with(word))
+            .map(|name| name.to_string())
+            .collect();
+        return (word_start, candidates);
+    }
+
+    // complete the last node within bridges (`3-GND,5-D`) or node lists (`GND;5V`)
+    let node_start = word
+        .rfind(['-', ',', ';'])
+        .map(|i| word_start + i + 1)
+        .unwrap_or(word_start);
+    let prefix = line[node_start..].to_ascii_uppercase();
+    let candidates = NAMED_NODES
+        .iter()
+        .map(|node| node.to_string())
+        .filter(|name| name.to_ascii_uppercase().starts_with(&prefix))
+        .collect();
+    (node_start, candidates)
+}
+
+/// Longest common prefix of all candidates
+fn common_prefix(candidates: &[String]) -> &str {
+    let Some(first) = candidates.first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map(|((i, _), _)| i)
+            .unwrap_or(len.min(candidate.len()))
+            .min(len);
+    }
+    &first[..len]
+}
+
+/// Reads lines, with line editing, history and completion when reading from a terminal
+struct Editor {
+    history: Vec<String>,
+    history_path: std::path::PathBuf,
+}
+
+impl Editor {
+    fn new(history_path: impl Into<std::path::PathBuf>) -> Self {
+        let history_path = history_path.into();
+        let history = std::fs::read_to_string(&history_path)
+            .map(|history| history.lines().map(|line| line.to_string()).collect())
+            .unwrap_or_default();
+        Self {
+            history,
+            history_path,
+        }
+    }
+
+    /// Read a line, returning `None` at the end of input
+    fn read_line(&mut self, prompt: &str) -> Result<Option<String>> {
+        let line = if std::io::stdin().is_terminal() {
+            self.edit_line(prompt)?
+        } else {
+            // e.g. commands piped in from a file: no prompt, no history
+            let mut line = String::new();
+            if std::io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            return Ok(Some(line));
+        };
+        if let Some(line) = &line {
+            self.add_history(line);
+        }
+        Ok(line)
+    }
+
+    fn add_history(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > HISTORY_SIZE {
+            self.history.drain(..self.history.len() - HISTORY_SIZE);
+        }
+        let saved = self
+            .history_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(&self.history_path, self.history.join("\n") + "\n"));
+        if let Err(e) = saved {
+            log::warn!("Failed to save history to {:?}: {}", self.history_path, e);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn edit_line(&mut self, prompt: &str) -> Result<Option<String>> {
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line))
+    }
+
+    /// Read a line in raw mode, with emacs-style key bindings
+    #[cfg(unix)]
+    fn edit_line(&mut self, prompt: &str) -> Result<Option<String>> {
+        use crate::terminal::{self, KeyCode, KeyModifiers, RawMode};
+        use crossterm::{
+            cursor::{MoveDown, MoveToColumn, MoveUp},
+            queue,
+            style::Print,
+            terminal::{Clear, ClearType},
+        };
+
+        let _raw_mode = RawMode::enable()?;
+        let mut stdout = std::io::stdout();
+        let prompt_len = prompt.chars().count();
+
+        let mut buffer: Vec<char> = vec![];
+        let mut cursor = 0;
+        // position in the history while browsing it, and the line that was entered before
+        let mut browsing: Option<(usize, Vec<char>)> = None;
+        // row of the terminal cursor, counted from the row where the prompt starts
+        let mut cursor_row = 0;
+
+        loop {
+            // redraw prompt and line, which may wrap over several rows
+            let width = crossterm::terminal::size()
+                .map(|(width, _)| width as usize)
+                .unwrap_or(80)
+                .max(1);
+            if cursor_row > 0 {
+                queue!(stdout, MoveUp(cursor_row as u16))?;
+            }
+            let line: String = buffer.iter().collect();
+            queue!(
+                stdout,
+                MoveToColumn(0),
+                Clear(ClearType::FromCursorDown),
+                Print(prompt),
+                Print(&line)
+            )?;
+            let end = prompt_len + buffer.len();
+            let end_row = end / width;
+            // `usize::is_multiple_of` needs Rust 1.87, older clippy does not know the lint
+            #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+            let at_line_end = end > 0 && end % width == 0;
+            if at_line_end {
+                // terminals only wrap when the next character is written
+                queue!(stdout, Print("\r\n"))?;
+            }
+            let position = prompt_len + cursor;
+            cursor_row = position / width;
+            if end_row > cursor_row {
+                queue!(stdout, MoveUp((end_row - cursor_row) as u16))?;
+            }
+            queue!(stdout, MoveToColumn((position % width) as u16))?;
+            stdout.flush()?;
+
+            let Some(key) = terminal::read_key(None)? else {
+                continue;
+            };
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            // moves the terminal cursor below the line, before writing something else
+            let mut leave_line = |text: &str| -> Result<()> {
+                if end_row > cursor_row {
+                    queue!(stdout, MoveDown((end_row - cursor_row) as u16))?;
+                }
+                write!(stdout, "\r\n{}", text)?;
+                cursor_row = 0;
+                Ok(())
+            };
+            match key.code {
+                KeyCode::Enter => {
+                    leave_line("")?;
+                    return Ok(Some(line));
+                }
+                // discard the line
+                KeyCode::Char('c') if ctrl => {
+                    leave_line("")?;
+                    buffer.clear();
+                    cursor = 0;
+                    browsing = None;
+                }
+                // end of input on an empty line, otherwise delete
+                KeyCode::Char('d') if ctrl && buffer.is_empty() => {
+                    leave_line("")?;
+                    return Ok(None);
+                }
+                KeyCode::Char('d') if ctrl && cursor < buffer.len() => {
+                    buffer.remove(cursor);
+                }
+                KeyCode::Delete if cursor < buffer.len() => {
+                    buffer.remove(cursor);
+                }
+                KeyCode::Home => cursor = 0,
+                KeyCode::Char('a') if ctrl => cursor = 0,
+                KeyCode::End => cursor = buffer.len(),
+                KeyCode::Char('e') if ctrl => cursor = buffer.len(),
+                // delete to the end and to the start of the line
+                KeyCode::Char('k') if ctrl => buffer.truncate(cursor),
+                KeyCode::Char('u') if ctrl => {
+                    buffer.drain(..cursor);
+                    cursor = 0;
+                }
+                KeyCode::Backspace if cursor > 0 => {
+                    cursor -= 1;
+                    buffer.remove(cursor);
+                }
+                KeyCode::Char('h') if ctrl && cursor > 0 => {
+                    cursor -= 1;
+                    buffer.remove(cursor);
+                }
+                KeyCode::Left if cursor > 0 => cursor -= 1,
+                KeyCode::Right if cursor < buffer.len() => cursor += 1,
+                KeyCode::Up | KeyCode::Down => {
+                    let (index, entered) = browsing
+                        .take()
+                        .unwrap_or_else(|| (self.history.len(), buffer.clone()));
+                    let index = if key.code == KeyCode::Up {
+                        index.saturating_sub(1)
+                    } else {
+                        (index + 1).min(self.history.len())
+                    };
+                    buffer = match self.history.get(index) {
+                        Some(line) => line.chars().collect(),
+                        None => entered.clone(),
+                    };
+                    cursor = buffer.len();
+                    browsing = Some((index, entered));
+                }
+                KeyCode::Tab => {
+                    let before: String = buffer[..cursor].iter().collect();
+                    let (start, candidates) = complete(&before);
+                    let prefix = common_prefix(&candidates);
+                    let typed = &before[start..];
+                    // nodes within bridges (`3-GND,5`) are not followed by a space
+                    let whole_word = start == 0 || before[..start].ends_with(char::is_whitespace);
+                    let start = before[..start].chars().count();
+                    // candidates may differ in case from what was typed (`gnd` -> `GND`)
+                    if candidates.len() == 1 || (!candidates.is_empty() && prefix != typed) {
+                        let mut completed: Vec<char> = prefix.chars().collect();
+                        if candidates.len() == 1 && whole_word {
+                            completed.push(' ');
+                        }
+                        buffer.splice(start..cursor, completed.iter().copied());
+                        cursor = start + completed.len();
+                    } else if candidates.len() > 1 {
+                        leave_line(&format!("{}\r\n", candidates.join("  ")))?;
+                    }
+                }
+                KeyCode::Char(c)
+                    if !key
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                {
+                    buffer.insert(cursor, c);
+                    cursor += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(complete("br"), (0, vec!["bridge".to_string()]));
+        assert_eq!(complete("bridge a"), (7, vec!["add".to_string()]));
+        let (start, candidates) = complete("bridge add 3-GND,5-nano_d1");
+        assert_eq!(start, 19);
+        assert_eq!(
+            candidates,
+            vec!["NANO_D1", "NANO_D10", "NANO_D11", "NANO_D12", "NANO_D13"]
+        );
+        assert_eq!(common_prefix(&candidates), "NANO_D1");
+        assert_eq!(complete("lightnet SUPPLY_3").1, vec!["SUPPLY_3V3"]);
+        // non-breaking space (Option+Space on macOS) is more than one byte
+        assert_eq!(complete("bridge\u{a0}a"), (8, vec!["add".to_string()]));
+    }
+}
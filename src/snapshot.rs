@@ -0,0 +1,273 @@
+//! Snapshots of the complete board state, which can be stored in named slots and restored later.
+
+use crate::{
+    device::Device,
+    diff::{self, Change},
+    logger::DeviceLogger,
+    state::BoardState,
+    types::{Color, SupplySwitchPos},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Version of the snapshot file format written by this version of jlctl
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Directory where snapshots are stored by default, relative to the working directory
+pub const DEFAULT_DIR: &str = ".jlctl/snapshots";
+
+/// The complete state of the board at some point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Snapshot {
+    /// Version of the file format
+    pub version: u32,
+    /// When the snapshot was taken (RFC 3339)
+    pub created: String,
+    #[serde(flatten)]
+    pub state: BoardState,
+    /// Colors of lights set via `lightnet`, by light name
+    #[serde(default)]
+    pub lights: BTreeMap<String, Color>,
+}
+
+/// Errors specific to snapshots
+#[derive(Debug)]
+pub enum Error {
+    /// Names may only contain letters, digits, `-`, `_` and `.`
+    InvalidName(String),
+    NotFound(String),
+    /// The snapshot was written by a newer version of jlctl
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidName(name) => write!(
+                f,
+                "Invalid snapshot name {:?}: use letters, digits, '-', '_' and '.'",
+                name
+            ),
+            Error::NotFound(name) => write!(f, "No snapshot named {:?}", name),
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "Snapshot has format version {}, but only up to {} is supported. Update jlctl to read it.",
+                version, FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Snapshot {
+    pub fn new(state: BoardState, lights: BTreeMap<String, Color>) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            created: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            state,
+            lights,
+        }
+    }
+
+    /// Take a snapshot of the device's current state.
+    ///
+    /// Lights are taken from the ones set through this `Device`, since they cannot be read back.
+    pub fn capture<L: DeviceLogger>(device: &mut Device<L>) -> Result<Self> {
+        Ok(Self::new(
+            BoardState::read(device)?,
+            device.lights().clone(),
+        ))
+    }
+
+    /// Parse a snapshot file, rejecting versions this version of jlctl does not know about
+    pub fn parse(json: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+        let Header { version } = serde_json::from_str(json)?;
+        if version > FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version).into());
+        }
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Write this snapshot to the device, then read the state back.
+    ///
+    /// The board state is applied first (supply switch, bridges, nets), then the lights.
+    /// Returns the differences between this snapshot's board state and the state read back, which is empty if everything was restored.
+    ///
+    /// Lights cannot be read back from the device, so they are not verified. Lights that are not
+    /// part of the snapshot are left as they are.
+    pub fn restore<L: DeviceLogger>(&self, device: &mut Device<L>) -> Result<Vec<Change>> {
+        self.state.apply(device)?;
+        for (name, color) in &self.lights {
+            device.lightnet(name.clone(), *color)?;
+        }
+        Ok(diff::states(&self.state, &BoardState::read(device)?))
+    }
+
+    /// Changes needed to get from this snapshot to `other`
+    pub fn diff(&self, other: &Snapshot) -> Vec<Change> {
+        let mut changes = diff::states(&self.state, &other.state);
+        changes.extend(diff::lights(&self.lights, &other.lights));
+        changes
+    }
+}
+
+/// Overview of a stored snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Summary {
+    pub name: String,
+    /// When the snapshot was taken (RFC 3339)
+    pub created: String,
+    /// Number of nets
+    pub nets: usize,
+    /// Number of bridges
+    pub bridges: usize,
+    pub supply_switch: SupplySwitchPos,
+}
+
+/// Named snapshots, stored as `<name>.json` files in a directory
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// List stored snapshots, sorted by name. Files that cannot be read are skipped.
+    pub fn list(&self) -> Result<Vec<Summary>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", self.dir)),
+        };
+        let mut summaries = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            let Some(name) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            match self.load(name) {
+                Ok(snapshot) => summaries.push(Summary {
+                    name: name.to_string(),
+                    created: snapshot.created,
+                    nets: snapshot.state.netlist.len(),
+                    bridges: snapshot.state.bridgelist.len(),
+                    supply_switch: snapshot.state.supply_switch,
+                }),
+                Err(e) => log::warn!("Skipping snapshot {:?}: {:#}", path, e),
+            }
+        }
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(summaries)
+    }
+
+    pub fn load(&self, name: &str) -> Result<Snapshot> {
+        let path = self.path(name)?;
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::NotFound(name.to_string()).into())
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        Snapshot::parse(&json).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    /// Store the snapshot, replacing an existing one with the same name
+    pub fn save(&self, name: &str, snapshot: &Snapshot) -> Result<()> {
+        let path = self.path(name)?;
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {:?}", self.dir))?;
+        // write to a temporary file first, so a failed write does not destroy the existing snapshot
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(snapshot)?)
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    /// Check that the name can be used for a snapshot
+    pub fn check_name(name: &str) -> Result<(), Error> {
+        let valid = !name.is_empty()
+            && name.len() <= 64
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(Error::InvalidName(name.to_string()));
+        }
+        Ok(())
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, Error> {
+        Self::check_name(name)?;
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Node;
+
+    #[test]
+    fn test_store() {
+        let dir = std::env::temp_dir().join(format!("jlctl-snapshots-{}", std::process::id()));
+        let store = Store::new(&dir);
+        assert!(store.list().unwrap().is_empty());
+
+        let snapshot = Snapshot::new(
+            BoardState {
+                netlist: vec![],
+                bridgelist: vec![(Node::Column(3), Node::Column(7))],
+                supply_switch: SupplySwitchPos::V3_3,
+            },
+            BTreeMap::from([("glow".to_string(), Color([0, 255, 0]))]),
+        );
+        store.save("blinky", &snapshot).unwrap();
+        assert_eq!(store.load("blinky").unwrap(), snapshot);
+        let summaries = store.list().unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].bridges, 1);
+
+        assert!(matches!(
+            store.load("missing").unwrap_err().downcast_ref(),
+            Some(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            store
+                .save("../escape", &snapshot)
+                .unwrap_err()
+                .downcast_ref(),
+            Some(Error::InvalidName(_))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let json = r#"{"version": 2, "created": "", "netlist": [], "bridgelist": [], "supply_switch": "5V"}"#;
+        assert!(matches!(
+            Snapshot::parse(json).unwrap_err().downcast_ref(),
+            Some(Error::UnsupportedVersion(2))
+        ));
+    }
+}
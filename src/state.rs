@@ -0,0 +1,37 @@
+use crate::{
+    device::Device,
+    logger::DeviceLogger,
+    types::{Bridgelist, Net, SupplySwitchPos},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Everything that is configured on the board: nets, bridges and the supply switch position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct BoardState {
+    pub netlist: Vec<Net>,
+    #[cfg_attr(feature = "server", schema(value_type = Vec<Bridge>))]
+    pub bridgelist: Bridgelist,
+    pub supply_switch: SupplySwitchPos,
+}
+
+impl BoardState {
+    /// Read the current state from the device
+    pub fn read<L: DeviceLogger>(device: &mut Device<L>) -> Result<Self> {
+        Ok(Self {
+            netlist: device.netlist()?,
+            bridgelist: device.bridgelist()?,
+            supply_switch: device.supply_switch()?,
+        })
+    }
+
+    /// Write this state to the device.
+    ///
+    /// The netlist is written last, so names and colors of nets are restored as well.
+    pub fn apply<L: DeviceLogger>(&self, device: &mut Device<L>) -> Result<()> {
+        device.set_supply_switch(self.supply_switch)?;
+        device.set_bridgelist(self.bridgelist.clone())?;
+        device.set_netlist(self.netlist.clone())
+    }
+}
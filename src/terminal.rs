@@ -0,0 +1,42 @@
+//! Keyboard input in raw mode, shared by `jlctl shell` and `jlctl tui`.
+
+use anyhow::{Context, Result};
+use crossterm::event::{self, Event, KeyEventKind};
+use std::time::{Duration, Instant};
+
+pub use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Enables raw mode, and leaves it again when dropped (also when returning early)
+pub struct RawMode;
+
+impl RawMode {
+    pub fn enable() -> Result<Self> {
+        crossterm::terminal::enable_raw_mode().context("Failed to enable raw mode")?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}
+
+/// Wait for the next key press, for at most `timeout` (or forever, if `None`).
+///
+/// Returns `None` when the time is up. Other events (key releases, resizes, mouse) are skipped.
+pub fn read_key(timeout: Option<Duration>) -> Result<Option<KeyEvent>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        if let Some(deadline) = deadline {
+            if !event::poll(deadline.saturating_duration_since(Instant::now()))? {
+                return Ok(None);
+            }
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Release {
+                return Ok(Some(key));
+            }
+        }
+    }
+}
@@ -0,0 +1,362 @@
+//! Full-screen terminal UI (`jlctl tui`): the board with net colors, the netlist, chip status and the device log.
+//!
+//! The state is polled periodically. Bridges are added and removed by selecting their two ends with the keyboard.
+
+use crate::{
+    board::Board,
+    device_manager::DeviceManager,
+    layout::{self, Row},
+    logger::{FileLogger, RecentLogger},
+    terminal::{self, KeyCode, KeyEvent, KeyModifiers, RawMode},
+    types::{Bridgelist, ChipStatus, Net, Node},
+};
+use anyhow::Result;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Number of device log lines kept for display
+pub const LOG_SIZE: usize = 500;
+
+/// Where the TUI reads the state from, and sends changes to
+pub enum Target<'a> {
+    /// The device, via the serial port. Its log is shown.
+    Device(
+        DeviceManager<RecentLogger<FileLogger>>,
+        RecentLogger<FileLogger>,
+    ),
+    /// Any other board (e.g. a `jlctl server`), described by the given name
+    // only constructed for remote mode. Not gated, because the lifetime would be unused without it.
+    #[cfg_attr(not(feature = "client"), allow(dead_code))]
+    Board(&'a mut dyn Board, String),
+}
+
+impl Target<'_> {
+    fn with_board<T>(&mut self, f: impl FnOnce(&mut dyn Board) -> Result<T>) -> Result<T> {
+        match self {
+            Target::Device(device_manager, _) => device_manager.with_device(|device| f(device)),
+            Target::Board(board, _) => f(*board),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Target::Device(device_manager, _) => device_manager
+                .connected_port()
+                .unwrap_or_else(|| "disconnected".to_string()),
+            Target::Board(_, name) => name.clone(),
+        }
+    }
+
+    fn log(&self) -> Option<Vec<String>> {
+        match self {
+            Target::Device(_, logger) => Some(logger.recent()),
+            Target::Board(..) => None,
+        }
+    }
+}
+
+/// Switches to the alternate screen in raw mode, and back when dropped
+struct Screen {
+    _raw_mode: RawMode,
+}
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        let raw_mode = RawMode::enable()?;
+        crossterm::execute!(
+            std::io::stdout(),
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::cursor::Hide
+        )?;
+        Ok(Self {
+            _raw_mode: raw_mode,
+        })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        // raw mode is left afterwards, when the field is dropped
+        let _ = crossterm::execute!(
+            std::io::stdout(),
+            crossterm::cursor::Show,
+            crossterm::terminal::LeaveAlternateScreen
+        );
+    }
+}
+
+/// Run the TUI until `q` is pressed. The state is read every `interval`.
+pub fn run(mut target: Target, interval: Duration) -> Result<()> {
+    // log messages would be drawn over the screen. Errors are shown in the UI instead.
+    log::set_max_level(log::LevelFilter::Off);
+    let _screen = Screen::enter()?;
+
+    let mut tui = Tui::new();
+    let mut next_refresh = Instant::now();
+    loop {
+        if Instant::now() >= next_refresh {
+            tui.refresh(&mut target);
+            next_refresh = Instant::now() + interval;
+        }
+        tui.draw(&target)?;
+        let timeout = next_refresh.saturating_duration_since(Instant::now());
+        let Some(key) = terminal::read_key(Some(timeout))? else {
+            continue;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('q') => break,
+            KeyCode::Char('c') if ctrl => break,
+            _ => tui.handle(key, &mut target),
+        }
+    }
+    Ok(())
+}
+
+struct Tui {
+    rows: Vec<Row>,
+    netlist: Vec<Net>,
+    bridgelist: Bridgelist,
+    chips: Vec<ChipStatus>,
+    /// Row and position within the row
+    cursor: (usize, usize),
+    /// First end of a bridge to add or remove
+    selected: Option<Node>,
+    /// Outcome of the last action
+    message: String,
+    /// Why the state could not be read, until it was read successfully again
+    error: Option<String>,
+}
+
+impl Tui {
+    fn new() -> Self {
+        let rows = layout::rows();
+        // start at the first breadboard column
+        let top = rows.iter().position(|row| row.name == "Top").unwrap_or(0);
+        Self {
+            rows,
+            netlist: vec![],
+            bridgelist: vec![],
+            chips: vec![],
+            cursor: (top, 0),
+            selected: None,
+            message: String::new(),
+            error: None,
+        }
+    }
+
+    /// Read the state. On errors, the last known state is kept.
+    fn refresh(&mut self, target: &mut Target) {
+        let state = target
+            .with_board(|board| Ok((board.netlist()?, board.bridgelist()?, board.chipstatus()?)));
+        match state {
+            Ok((netlist, bridgelist, chips)) => {
+                self.netlist = netlist;
+                self.bridgelist = bridgelist;
+                self.chips = chips;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("Error: {:#}", e)),
+        }
+    }
+
+    fn cursor_node(&self) -> Node {
+        let (row, index) = self.cursor;
+        self.rows[row].nodes[index]
+    }
+
+    fn handle(&mut self, key: KeyEvent, target: &mut Target) {
+        let (row, index) = self.cursor;
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.move_to(row.saturating_sub(1), index),
+            KeyCode::Down | KeyCode::Char('j') => self.move_to(row + 1, index),
+            KeyCode::Left | KeyCode::Char('h') => self.move_to(row, index.saturating_sub(1)),
+            KeyCode::Right | KeyCode::Char('l') => self.move_to(row, index + 1),
+            KeyCode::Home => self.move_to(row, 0),
+            KeyCode::End => self.move_to(row, usize::MAX),
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                let node = self.cursor_node();
+                if self.selected == Some(node) {
+                    self.selected = None;
+                    self.message.clear();
+                } else {
+                    self.selected = Some(node);
+                    self.message = format!(
+                        "Selected {}. Move to the other end, then press a to add or x to remove the bridge.",
+                        node
+                    );
+                }
+            }
+            KeyCode::Char('c') | KeyCode::Esc => {
+                self.selected = None;
+                self.message.clear();
+            }
+            KeyCode::Char('a' | 'x') | KeyCode::Delete => {
+                let add = key.code == KeyCode::Char('a');
+                let to = self.cursor_node();
+                let Some(from) = self.selected.filter(|from| *from != to) else {
+                    self.message = "Select two different nodes first".to_string();
+                    return;
+                };
+                let result = target.with_board(|board| {
+                    if add {
+                        board.add_bridges(vec![(from, to)])
+                    } else {
+                        board.remove_bridges(vec![(from, to)])
+                    }
+                });
+                self.message = match result {
+                    Ok(_) if add => format!("Added bridge {}-{}", from, to),
+                    Ok(_) => format!("Removed bridge {}-{}", from, to),
+                    Err(e) => format!("Error: {:#}", e),
+                };
+                self.selected = None;
+                self.refresh(target);
+            }
+            KeyCode::Char('r') => self.refresh(target),
+            _ => {}
+        }
+    }
+
+    fn move_to(&mut self, row: usize, index: usize) {
+        let row = row.min(self.rows.len() - 1);
+        let index = index.min(self.rows[row].nodes.len() - 1);
+        self.cursor = (row, index);
+    }
+
+    fn draw(&self, target: &Target) -> Result<()> {
+        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        let (width, height) = (width as usize, height as usize);
+        let mut lines = vec![
+            format!(
+                "\x1b[1m{}\x1b[0m",
+                truncate(&format!("jlctl tui - {}", target.name()), width)
+            ),
+            String::new(),
+        ];
+
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let cells = layout::draw_cells(
+                row,
+                width.saturating_sub(layout::NAME_WIDTH),
+                |index, node, cell_width| {
+                    let text = format!("{:>1$}", layout::label(node), cell_width);
+                    self.cell(&text, node, self.cursor == (row_index, index))
+                },
+            );
+            lines.push(format!("{:<2$}{}", row.name, cells, layout::NAME_WIDTH));
+        }
+
+        let node = self.cursor_node();
+        let net = layout::net_of(&self.netlist, node)
+            .map(|net| format!(" (net {} {:?})", net.index, net.name))
+            .unwrap_or_default();
+        lines.push(String::new());
+        lines.push(truncate(&format!("Cursor: {}{}", node, net), width));
+        lines.push(truncate(
+            self.error.as_ref().unwrap_or(&self.message),
+            width,
+        ));
+        lines.push(format!(
+            "\x1b[2m{}\x1b[0m",
+            truncate(
+                "arrows/hjkl/home/end move, space select, a add bridge, x/del remove bridge, c/esc clear, r refresh, q quit",
+                width
+            )
+        ));
+        lines.push(String::new());
+
+        let bridges: Vec<String> = self
+            .bridgelist
+            .iter()
+            .map(|(from, to)| format!("{}-{}", from, to))
+            .collect();
+        lines.push(truncate(
+            &format!("Bridges ({}): {}", bridges.len(), bridges.join(", ")),
+            width,
+        ));
+        let chips: Vec<String> = self
+            .chips
+            .iter()
+            .map(|chip| {
+                let used = |status: &[i8]| status.iter().filter(|s| **s != -1).count();
+                format!(
+                    "{} {}/{}",
+                    chip.char,
+                    used(&chip.x_status),
+                    used(&chip.y_status)
+                )
+            })
+            .collect();
+        lines.push(truncate(
+            &format!("Chips (X/Y lines in use): {}", chips.join("  ")),
+            width,
+        ));
+
+        // split the remaining space between nets and the log, keeping a few lines for the log
+        let log = target.log();
+        let available = height.saturating_sub(lines.len() + 2);
+        let log_lines = if log.is_some() { available.min(5) } else { 0 };
+        let net_lines = self.netlist.len().min(available - log_lines);
+        let log_lines = available - net_lines;
+
+        let hidden = self.netlist.len() - net_lines;
+        lines.push(if hidden > 0 {
+            format!("\x1b[1mNets\x1b[0m ({} not shown)", hidden)
+        } else {
+            "\x1b[1mNets\x1b[0m".to_string()
+        });
+        for net in &self.netlist[..net_lines] {
+            let nodes: Vec<String> = net.nodes.iter().map(|node| node.to_string()).collect();
+            let text = format!(" {:>2} {:<12} {}", net.index, net.name, nodes.join(", "));
+            lines.push(format!(
+                "{}  \x1b[0m{}",
+                layout::paint(net.color),
+                truncate(&text, width.saturating_sub(2))
+            ));
+        }
+
+        if let Some(log) = log {
+            lines.push("\x1b[1mDevice log\x1b[0m".to_string());
+            for line in &log[log.len().saturating_sub(log_lines)..] {
+                lines.push(format!("\x1b[2m{}\x1b[0m", truncate(line, width)));
+            }
+        }
+
+        let mut screen = String::from("\x1b[H");
+        for (i, line) in lines.iter().take(height).enumerate() {
+            if i > 0 {
+                screen.push_str("\r\n");
+            }
+            screen.push_str(line);
+            screen.push_str("\x1b[K");
+        }
+        screen.push_str("\x1b[J");
+        let mut stdout = std::io::stdout();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// Draw a node in the color of its net, highlighting the cursor and the selection
+    fn cell(&self, text: &str, node: Node, cursor: bool) -> String {
+        let mut style = String::new();
+        if let Some(net) = layout::net_of(&self.netlist, node) {
+            style.push_str(&layout::paint(net.color));
+        }
+        if self.selected == Some(node) {
+            style.push_str("\x1b[1;4m");
+        }
+        if cursor {
+            style.push_str("\x1b[7m");
+        }
+        if style.is_empty() {
+            return text.to_string();
+        }
+        format!("{}{}\x1b[0m", style, text)
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
@@ -4,6 +4,7 @@ use crate::parser;
 
 /// Represents a named set of connected Nodes
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
 pub struct Net {
     pub index: u8,
     pub number: u8,
@@ -14,6 +15,71 @@ pub struct Net {
     pub name: String,
 }
 
+/// A new net, to be added to the netlist. Index and number are assigned when adding it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct NewNet {
+    pub nodes: Vec<Node>,
+    pub color: Color,
+    pub name: Option<String>,
+}
+
+impl NewNet {
+    /// Add this net to the given netlist, using the next free index
+    ///
+    /// Returns `None` (leaving the netlist unchanged) if the highest index is already taken.
+    pub fn add_to(self, netlist: &mut Vec<Net>) -> Option<u8> {
+        let index = netlist
+            .iter()
+            .map(|net| net.index)
+            .max()
+            .unwrap_or(0)
+            .checked_add(1)?;
+        netlist.push(Net {
+            index,
+            number: index,
+            nodes: self.nodes,
+            special: false,
+            color: self.color,
+            machine: false,
+            name: self.name.unwrap_or_else(|| format!("Net {}", index)),
+        });
+        Some(index)
+    }
+}
+
+/// Partial update of a single net. Fields that are not given are left unchanged.
+#[cfg(any(feature = "server", feature = "client", test))]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct NetPatch {
+    pub name: Option<String>,
+    pub color: Option<Color>,
+    #[serde(default)]
+    pub add_nodes: Vec<Node>,
+    #[serde(default)]
+    pub remove_nodes: Vec<Node>,
+}
+
+impl Net {
+    /// Apply the given patch to this net
+    #[cfg(any(feature = "server", test))]
+    pub fn patch(&mut self, patch: NetPatch) {
+        if let Some(name) = patch.name {
+            self.name = name;
+        }
+        if let Some(color) = patch.color {
+            self.color = color;
+        }
+        for node in patch.add_nodes {
+            if !self.nodes.contains(&node) {
+                self.nodes.push(node);
+            }
+        }
+        self.nodes.retain(|node| !patch.remove_nodes.contains(node));
+    }
+}
+
 /// this is the net format expected by the device, for `::netlist` input.
 /// It's currently different from the `Net` format used internally by jlctl.
 #[derive(Serialize)]
@@ -53,7 +119,7 @@ impl From<Net> for TmpNet {
 }
 
 /// A message received from the jumperless
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Message {
     Ok(Option<u32>),
     Error(Option<u32>),
@@ -94,6 +160,26 @@ impl std::str::FromStr for SupplySwitchPos {
     }
 }
 
+impl Serialize for SupplySwitchPos {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for SupplySwitchPos {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| serde::de::Error::custom(format!("Unknown supply switch position: {}", s)))
+    }
+}
+
 impl std::fmt::Display for SupplySwitchPos {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -111,6 +197,14 @@ impl std::fmt::Display for SupplySwitchPos {
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Color(pub [u8; 3]);
 
+impl Color {
+    /// Whether black text is more readable than white text on this color
+    pub fn is_light(&self) -> bool {
+        let Color([r, g, b]) = *self;
+        (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000 > 128
+    }
+}
+
 impl From<Color> for u32 {
     fn from(Color([r, g, b]): Color) -> Self {
         ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
@@ -226,6 +320,51 @@ pub enum Node {
     Column(u8),
 }
 
+/// All nodes that have a name, i.e. every node except for [`Node::Column`]
+pub const NAMED_NODES: [Node; 38] = {
+    use Node::*;
+    [
+        GND,
+        SUPPLY_5V,
+        SUPPLY_3V3,
+        DAC0,
+        DAC1,
+        ISENSE_MINUS,
+        ISENSE_PLUS,
+        ADC0,
+        ADC1,
+        ADC2,
+        ADC3,
+        NANO_D0,
+        NANO_D1,
+        NANO_D2,
+        NANO_D3,
+        NANO_D4,
+        NANO_D5,
+        NANO_D6,
+        NANO_D7,
+        NANO_D8,
+        NANO_D9,
+        NANO_D10,
+        NANO_D11,
+        NANO_D12,
+        NANO_D13,
+        NANO_A0,
+        NANO_A1,
+        NANO_A2,
+        NANO_A3,
+        NANO_A4,
+        NANO_A5,
+        NANO_A6,
+        NANO_A7,
+        NANO_RESET,
+        NANO_AREF,
+        RP_GPIO_0,
+        RP_UART_Rx,
+        RP_UART_Tx,
+    ]
+};
+
 impl Node {
     /// Construct Node for given column number, if it is in the valid range.
     pub fn col(n: u8) -> Option<Self> {
@@ -395,9 +534,13 @@ impl std::fmt::Display for Node {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
 pub struct ChipStatus {
+    #[cfg_attr(feature = "server", schema(value_type = String))]
     pub char: char,
+    #[cfg_attr(feature = "server", schema(value_type = Vec<i8>))]
     pub x_status: [i8; 16],
+    #[cfg_attr(feature = "server", schema(value_type = Vec<i8>))]
     pub y_status: [i8; 8],
 }
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use std::collections::HashMap;
 
 use crate::types::{Net, Node};
@@ -12,12 +13,110 @@ const SPECIAL_NETS: [(u8, &str, Node); 7] = [
     (7, "I Sense -", Node::ISENSE_MINUS),
 ];
 
-pub fn netlist(netlist: Vec<Net>) -> anyhow::Result<Vec<Net>> {
+/// The special nets of `netlist`, without any other nodes connected to them.
+///
+/// Their colors are kept as they are. Special nets that are missing are left out, so validating fails later.
+pub fn special_nets(netlist: &[Net]) -> Vec<Net> {
+    SPECIAL_NETS
+        .iter()
+        .filter_map(|(index, _, node)| {
+            let net = netlist.iter().find(|net| net.index == *index)?;
+            Some(Net {
+                nodes: vec![*node],
+                ..net.clone()
+            })
+        })
+        .collect()
+}
+
+/// The special nets, as the device has them after a reset.
+///
+/// The colors are taken from the device's `::netlist` output (see the parser tests).
+pub fn initial_netlist() -> Vec<Net> {
+    use crate::types::Color;
+    let colors = [
+        [0x00, 0x1c, 0x04],
+        [0x1c, 0x07, 0x02],
+        [0x1c, 0x01, 0x07],
+        [0x23, 0x11, 0x11],
+        [0x23, 0x09, 0x13],
+        [0x23, 0x23, 0x23],
+        [0x23, 0x23, 0x23],
+    ];
+    SPECIAL_NETS
+        .iter()
+        .zip(colors)
+        .map(|((index, name, node), color)| Net {
+            index: *index,
+            number: *index,
+            nodes: vec![*node],
+            special: true,
+            color: Color(color),
+            machine: false,
+            name: name.to_string(),
+        })
+        .collect()
+}
+
+/// Check that a single net has at least one node.
+///
+/// This is only required of nets that are created or changed one at a time (`POST /nets`, `PATCH /nets/{index}`).
+/// [`netlist`] accepts empty nets, as the device does.
+#[cfg(any(feature = "server", test))]
+pub fn has_nodes(net: &Net) -> Result<(), Error> {
+    if net.nodes.is_empty() {
+        return Err(Error(vec![Problem {
+            index: net.index,
+            field: "nodes",
+            message: format!("Net {} (index: {}) has no nodes", net.name, net.index),
+        }]));
+    }
+    Ok(())
+}
+
+/// A single problem found while validating a netlist
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Problem {
+    /// Index of the net that has the problem
+    pub index: u8,
+    /// Name of the offending field
+    pub field: &'static str,
+    /// Human readable description
+    pub message: String,
+}
+
+/// Returned by [`netlist`], listing all problems that were found
+#[derive(Debug)]
+pub struct Error(pub Vec<Problem>);
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, problem) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", problem.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub fn netlist(netlist: Vec<Net>) -> Result<Vec<Net>, Error> {
     let mut by_index = HashMap::new();
+    let mut problems = vec![];
+    let mut problem = |index: u8, field: &'static str, message: String| {
+        problems.push(Problem {
+            index,
+            field,
+            message,
+        })
+    };
 
     for net in &netlist {
         if by_index.contains_key(&net.index) {
-            return Err(anyhow::anyhow!("Duplicate index {}", net.index));
+            problem(net.index, "index", format!("Duplicate index {}", net.index));
         }
         by_index.insert(net.index, net);
     }
@@ -25,31 +124,136 @@ pub fn netlist(netlist: Vec<Net>) -> anyhow::Result<Vec<Net>> {
     for (index, name, node) in &SPECIAL_NETS {
         if let Some(net) = by_index.get(index) {
             if net.name.as_str() != *name {
-                return Err(anyhow::anyhow!(
-                    "Special net {} (index: {}) cannot be renamed",
-                    name,
-                    index
-                ));
+                problem(
+                    *index,
+                    "name",
+                    format!("Special net {} (index: {}) cannot be renamed", name, index),
+                );
             }
             if !net.nodes.contains(node) {
-                return Err(anyhow::anyhow!(
-                    "Special net {} (index: {}) is missing node {:?}",
-                    name,
-                    index,
-                    node
-                ));
+                problem(
+                    *index,
+                    "nodes",
+                    format!(
+                        "Special net {} (index: {}) is missing node {:?}",
+                        name, index, node
+                    ),
+                );
             }
         } else {
-            return Err(anyhow::anyhow!(
-                "Special net {} (index: {}) missing",
-                name,
-                index
-            ));
+            problem(
+                *index,
+                "index",
+                format!("Special net {} (index: {}) missing", name, index),
+            );
         }
     }
 
-    Ok(netlist)
+    if problems.is_empty() {
+        Ok(netlist)
+    } else {
+        Err(Error(problems))
+    }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::types::{Color, NetPatch, NewNet};
+
+    #[test]
+    fn test_initial_netlist() {
+        assert!(netlist(initial_netlist()).is_ok());
+    }
+
+    #[test]
+    fn test_special_nets() {
+        let mut nets = initial_netlist();
+        nets[0].color = Color([0x10, 0x20, 0x30]);
+        nets[0].nodes.push(Node::Column(10));
+        NewNet {
+            nodes: vec![Node::Column(3), Node::Column(17)],
+            color: Color([0xff, 0, 0]),
+            name: None,
+        }
+        .add_to(&mut nets);
+        nets.retain(|net| net.index != 3);
+
+        let special = special_nets(&nets);
+        assert_eq!(special.len(), 6);
+        assert_eq!(special[0].nodes, vec![Node::GND]);
+        assert_eq!(special[0].color, Color([0x10, 0x20, 0x30]));
+    }
+
+    #[test]
+    fn test_special_net_missing() {
+        let mut nets = initial_netlist();
+        nets.retain(|net| net.index != 3);
+        assert!(netlist(nets).is_err());
+    }
+
+    #[test]
+    fn test_special_net_renamed() {
+        let mut nets = initial_netlist();
+        nets[0].patch(NetPatch {
+            name: Some("Ground".to_string()),
+            remove_nodes: vec![Node::GND],
+            ..Default::default()
+        });
+        let Error(problems) = netlist(nets).unwrap_err();
+        let fields: Vec<(u8, &str)> = problems.iter().map(|p| (p.index, p.field)).collect();
+        assert_eq!(fields, vec![(1, "name"), (1, "nodes")]);
+    }
+
+    #[test]
+    fn test_new_net() {
+        let mut nets = initial_netlist();
+        let index = NewNet {
+            nodes: vec![Node::Column(3), Node::Column(17)],
+            color: Color([0xff, 0, 0]),
+            name: None,
+        }
+        .add_to(&mut nets);
+        assert_eq!(index, Some(8));
+        assert_eq!(nets[7].name, "Net 8");
+        assert!(netlist(nets).is_ok());
+    }
+
+    #[test]
+    fn test_new_net_after_highest_index() {
+        let mut nets = initial_netlist();
+        nets.push(Net {
+            index: 255,
+            number: 255,
+            nodes: vec![Node::Column(3), Node::Column(17)],
+            special: false,
+            color: Color([0xff, 0, 0]),
+            machine: false,
+            name: "Net 255".to_string(),
+        });
+        let new_net = NewNet {
+            nodes: vec![Node::Column(4), Node::Column(18)],
+            color: Color([0, 0xff, 0]),
+            name: None,
+        };
+        assert_eq!(new_net.add_to(&mut nets), None);
+        assert_eq!(nets.len(), 8);
+    }
+
+    #[test]
+    fn test_net_without_nodes() {
+        let mut nets = initial_netlist();
+        NewNet {
+            nodes: vec![Node::Column(3)],
+            color: Color([0xff, 0, 0]),
+            name: None,
+        }
+        .add_to(&mut nets);
+        nets[7].patch(NetPatch {
+            remove_nodes: vec![Node::Column(3)],
+            ..Default::default()
+        });
+        assert!(has_nodes(&nets[7]).is_err());
+        assert!(netlist(nets).is_ok());
+    }
+}